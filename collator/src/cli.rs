@@ -17,7 +17,16 @@
 //! Here we define the CLI arguments needed to run the collator node.
 
 use clap::Parser;
+use pba_parachain_collator::GenesisConfig;
+use polkadot_service::IdentifyVariant;
 use sc_cli::{RuntimeVersion, SubstrateCli};
+use sc_service::config::DatabaseSource;
+use sp_core::Bytes;
+use std::{
+	fs,
+	io::{self, Write},
+	path::PathBuf,
+};
 
 /// Sub-commands supported by the collator.
 ///
@@ -29,18 +38,253 @@ pub enum Subcommand {
 	#[command(name = "export-genesis-state")]
 	ExportGenesisState(ExportGenesisStateCommand),
 
+	/// Export what registering the parachain on the relay chain requires, as JSON.
+	#[command(name = "export-genesis-config")]
+	ExportGenesisConfig(ExportGenesisConfigCommand),
+
 	/// Export the genesis wasm of the parachain.
 	#[command(name = "export-genesis-wasm")]
 	ExportGenesisWasm(ExportGenesisWasmCommand),
+
+	/// Execute a block on top of a parent head offline, without a relay chain.
+	#[command(name = "validate-block")]
+	ValidateBlock(ValidateBlockCommand),
+
+	/// Check a PoV file against a parent head offline, without a relay chain.
+	#[command(name = "verify-pov")]
+	VerifyPov(VerifyPovCommand),
+
+	/// Remove the relay-chain database and the parachain state.
+	#[command(name = "purge-chain")]
+	PurgeChain(PurgeChainCmd),
+
+	/// Generate a new collator key.
+	#[command(name = "generate-collator-key")]
+	GenerateCollatorKey(GenerateCollatorKeyCommand),
+
+	/// Measure the throughput of executing blocks.
+	#[command(name = "benchmark")]
+	Benchmark(BenchmarkCommand),
+
+	/// Execute a sequence of blocks offline, printing every resulting head.
+	#[command(name = "replay")]
+	Replay(ReplayCommand),
+
+	/// Print the state of every head known to the collator.
+	#[command(name = "dump-state")]
+	DumpState(DumpStateCommand),
+}
+
+/// Parameters selecting the genesis of the parachain.
+#[derive(Debug, Parser)]
+#[group(skip)]
+pub struct GenesisConfigParams {
+	/// JSON file with the genesis configuration of the parachain.
+	///
+	/// For example `{ "state": 0, "add": 7, "mode": "Wrapping" }`, missing fields take their
+	/// default. All collators of the chain must use the same configuration.
+	#[arg(long)]
+	pub genesis_config: Option<PathBuf>,
+}
+
+impl GenesisConfigParams {
+	/// Load the genesis configuration, or the default one if no file was given.
+	pub fn load(&self) -> sc_cli::Result<GenesisConfig> {
+		let path = match &self.genesis_config {
+			Some(path) => path,
+			None => return Ok(GenesisConfig::default()),
+		};
+
+		serde_json::from_slice(&fs::read(path)?)
+			.map_err(|e| sc_cli::Error::Input(format!("Invalid genesis configuration: {}", e)))
+	}
 }
 
 /// Command for exporting the genesis state of the parachain
 #[derive(Debug, Parser)]
-pub struct ExportGenesisStateCommand {}
+pub struct ExportGenesisStateCommand {
+	#[allow(missing_docs)]
+	#[command(flatten)]
+	pub genesis: GenesisConfigParams,
+
+	/// Output file name or stdout if unspecified.
+	#[arg(long)]
+	pub output: Option<PathBuf>,
+
+	/// Write the SCALE encoded genesis head as raw bytes instead of `0x` prefixed hex.
+	#[arg(long)]
+	pub raw: bool,
+}
+
+/// Command for exporting the registration parameters of the parachain.
+#[derive(Debug, Parser)]
+pub struct ExportGenesisConfigCommand {
+	#[allow(missing_docs)]
+	#[command(flatten)]
+	pub genesis: GenesisConfigParams,
+
+	/// Id of the parachain to register.
+	#[arg(long)]
+	pub parachain_id: u32,
+
+	/// Output file name or stdout if unspecified.
+	#[arg(long)]
+	pub output: Option<PathBuf>,
+}
 
 /// Command for exporting the genesis wasm file.
 #[derive(Debug, Parser)]
-pub struct ExportGenesisWasmCommand {}
+pub struct ExportGenesisWasmCommand {
+	/// Print the blake2-256 hash of the wasm as hex instead of the wasm itself.
+	#[arg(long)]
+	pub hash_only: bool,
+}
+
+/// Command for executing a block offline.
+#[derive(Debug, Parser)]
+pub struct ValidateBlockCommand {
+	/// SCALE encoded parent head, as hex.
+	#[arg(long, value_parser = parse_hex)]
+	pub parent_head: Bytes,
+
+	/// SCALE encoded block data, as hex.
+	#[arg(long, value_parser = parse_hex)]
+	pub block_data: Bytes,
+}
+
+/// Command for verifying a PoV.
+#[derive(Debug, Parser)]
+pub struct VerifyPovCommand {
+	/// File containing the SCALE encoded PoV, compressed or not.
+	#[arg(long)]
+	pub pov: PathBuf,
+
+	/// SCALE encoded parent head, as hex.
+	#[arg(long, value_parser = parse_hex)]
+	pub parent_head: Bytes,
+}
+
+/// Command for generating a collator key.
+#[derive(Debug, Parser)]
+pub struct GenerateCollatorKeyCommand {
+	/// Write the secret phrase to this file, to be passed to `--collator-key-file`.
+	///
+	/// The file is created readable by its owner only. An existing file is never overwritten.
+	#[arg(long)]
+	pub output: Option<PathBuf>,
+}
+
+/// Command for benchmarking block execution.
+#[derive(Debug, Parser)]
+pub struct BenchmarkCommand {
+	/// Number of blocks to execute per run.
+	#[arg(long, default_value_t = 10_000)]
+	pub iterations: u32,
+}
+
+/// Command for replaying blocks.
+#[derive(Debug, Parser)]
+pub struct ReplayCommand {
+	/// File containing the SCALE encoded block data of the blocks to replay, back to back.
+	#[arg(long)]
+	pub blocks: PathBuf,
+
+	/// SCALE encoded head to replay the blocks on top of, as hex.
+	///
+	/// Defaults to the genesis head.
+	#[arg(long, value_parser = parse_hex)]
+	pub from_head: Option<Bytes>,
+
+	#[allow(missing_docs)]
+	#[command(flatten)]
+	pub genesis: GenesisConfigParams,
+}
+
+/// Command for dumping the parachain state.
+#[derive(Debug, Parser)]
+pub struct DumpStateCommand {
+	#[allow(missing_docs)]
+	#[command(flatten)]
+	pub shared_params: sc_cli::SharedParams,
+
+	/// Print the entries as JSON instead of a table.
+	#[arg(long)]
+	pub json: bool,
+
+	#[allow(missing_docs)]
+	#[command(flatten)]
+	pub genesis: GenesisConfigParams,
+}
+
+impl sc_cli::CliConfiguration for DumpStateCommand {
+	fn shared_params(&self) -> &sc_cli::SharedParams {
+		&self.shared_params
+	}
+}
+
+/// Command for removing the databases of the collator.
+#[derive(Debug, Parser)]
+pub struct PurgeChainCmd {
+	#[allow(missing_docs)]
+	#[command(flatten)]
+	pub base: sc_cli::PurgeChainCmd,
+}
+
+impl PurgeChainCmd {
+	/// Remove the relay-chain `database` and the parachain state at `state_db`, if any.
+	///
+	/// Asks for confirmation first, unless `-y` was passed.
+	pub fn run(&self, database: DatabaseSource, state_db: Option<PathBuf>) -> sc_cli::Result<()> {
+		let db_path = database.path().ok_or_else(|| {
+			sc_cli::Error::Input("Cannot purge a custom database implementation".into())
+		})?;
+		let paths = std::iter::once(db_path.to_path_buf()).chain(state_db).collect::<Vec<_>>();
+
+		if !self.base.yes {
+			for path in &paths {
+				println!("{}", path.display());
+			}
+			print!("Are you sure to remove? [y/N]: ");
+			io::stdout().flush()?;
+
+			let mut input = String::new();
+			io::stdin().read_line(&mut input)?;
+			match input.trim().chars().next() {
+				Some('y') | Some('Y') => {},
+				_ => {
+					println!("Aborted");
+					return Ok(())
+				},
+			}
+		}
+
+		for path in &paths {
+			match fs::remove_dir_all(path) {
+				Ok(()) => println!("{:?} removed.", path),
+				Err(e) if e.kind() == io::ErrorKind::NotFound =>
+					eprintln!("{:?} did not exist.", path),
+				Err(e) => return Err(e.into()),
+			}
+		}
+
+		Ok(())
+	}
+}
+
+impl sc_cli::CliConfiguration for PurgeChainCmd {
+	fn shared_params(&self) -> &sc_cli::SharedParams {
+		&self.base.shared_params
+	}
+
+	fn database_params(&self) -> Option<&sc_cli::DatabaseParams> {
+		Some(&self.base.database_params)
+	}
+}
+
+/// Parse a hex string, optionally `0x` prefixed.
+fn parse_hex(hex: &str) -> Result<Bytes, String> {
+	sp_core::bytes::from_hex(hex).map(Bytes).map_err(|e| e.to_string())
+}
 
 #[allow(missing_docs)]
 #[derive(Debug, Parser)]
@@ -50,9 +294,77 @@ pub struct RunCmd {
 	#[clap(flatten)]
 	pub base: sc_cli::RunCmd,
 
-	/// Id of the parachain this collator collates for.
+	/// Relay chain to connect to: `rococo`, `westend` or `kusama` (optionally suffixed with
+	/// `-local` or `-staging`), or the path to a chain spec of one of them.
+	///
+	/// The collator runs as a node of the relay chain, so this is the same as `--chain` and
+	/// can't be combined with it. The runtime is picked from the id of the spec, with unknown ids
+	/// falling back to Rococo.
+	#[arg(long, conflicts_with = "chain")]
+	pub relay_chain: Option<String>,
+
+	/// Id of the parachain this collator collates for, required to run the collator.
 	#[arg(long)]
 	pub parachain_id: Option<u32>,
+
+	/// Path to a file containing the seed of the collator key.
+	///
+	/// A random key is generated if not given.
+	#[arg(long)]
+	pub collator_key_file: Option<PathBuf>,
+
+	/// Seed of the collator key, i.e. a secret phrase, a hex seed or a secret URI.
+	///
+	/// Makes the collator id reproducible across runs. A random key is generated if not given.
+	#[arg(long, conflicts_with = "collator_key_file", value_parser = parse_collator_seed)]
+	pub collator_seed: Option<String>,
+
+	/// Hand out the PoV of the collations uncompressed, e.g. to inspect its bytes.
+	#[arg(long)]
+	pub no_pov_compression: bool,
+
+	/// Compress the PoV of the collations with zstd at the given level instead of the default one.
+	#[arg(long, conflicts_with = "no_pov_compression")]
+	pub pov_compression_level: Option<i32>,
+
+	/// Re-execute every produced block with the validation logic before collating it.
+	///
+	/// Catches divergences between the collator and the validation code, at the cost of
+	/// executing every block twice.
+	#[arg(long)]
+	pub verify_collations: bool,
+
+	/// Log level of the collator itself, e.g. `debug`, independently of the relay-chain node.
+	///
+	/// Defaults to the global log level.
+	#[arg(long, value_parser = parse_log_level)]
+	pub collator_log_level: Option<log::LevelFilter>,
+
+	/// Amount added to the state by every produced block.
+	///
+	/// Takes precedence over the genesis configuration and the `PBA_ADD` environment variable.
+	#[arg(long)]
+	pub add: Option<u64>,
+
+	/// Seconds between two logs of the collator's health, `0` to disable them.
+	#[arg(long, default_value_t = 60)]
+	pub health_log_interval: u64,
+
+	#[allow(missing_docs)]
+	#[command(flatten)]
+	pub genesis: GenesisConfigParams,
+}
+
+/// Parse a log level such as `info` or `trace`.
+fn parse_log_level(level: &str) -> Result<log::LevelFilter, String> {
+	level.parse().map_err(|_| format!("invalid log level: {}", level))
+}
+
+/// Check that the given seed can produce a collator key.
+fn parse_collator_seed(seed: &str) -> Result<String, String> {
+	pba_parachain_collator::collator_key_from_seed(seed)
+		.map(|_| seed.to_owned())
+		.map_err(|e| e.to_string())
 }
 
 #[allow(missing_docs)]
@@ -102,16 +414,44 @@ impl SubstrateCli for Cli {
 			"rococo-local" =>
 				Box::new(polkadot_service::chain_spec::rococo_local_testnet_config()?),
 			"rococo" => Box::new(polkadot_service::chain_spec::rococo_config()?),
+			"westend-staging" =>
+				Box::new(polkadot_service::chain_spec::westend_staging_testnet_config()?),
+			"westend-local" =>
+				Box::new(polkadot_service::chain_spec::westend_local_testnet_config()?),
+			"westend" => Box::new(polkadot_service::chain_spec::westend_config()?),
+			"kusama-staging" =>
+				Box::new(polkadot_service::chain_spec::kusama_staging_testnet_config()?),
+			"kusama-local" =>
+				Box::new(polkadot_service::chain_spec::kusama_local_testnet_config()?),
+			"kusama" => Box::new(polkadot_service::chain_spec::kusama_config()?),
 			path => {
 				let path = std::path::PathBuf::from(path);
-				Box::new(polkadot_service::RococoChainSpec::from_json_file(path)?)
+
+				// Load it generically first to find out which relay chain the spec is for.
+				let chain_spec =
+					Box::new(polkadot_service::PolkadotChainSpec::from_json_file(path.clone())?)
+						as Box<dyn sc_service::ChainSpec>;
+
+				if chain_spec.is_kusama() {
+					Box::new(polkadot_service::KusamaChainSpec::from_json_file(path)?)
+				} else if chain_spec.is_westend() {
+					Box::new(polkadot_service::WestendChainSpec::from_json_file(path)?)
+				} else {
+					Box::new(polkadot_service::RococoChainSpec::from_json_file(path)?)
+				}
 			},
 		})
 	}
 
 	fn native_runtime_version(
-		_spec: &Box<dyn polkadot_service::ChainSpec>,
+		spec: &Box<dyn polkadot_service::ChainSpec>,
 	) -> &'static RuntimeVersion {
-		&polkadot_service::rococo_runtime::VERSION
+		if spec.is_kusama() {
+			&polkadot_service::kusama_runtime::VERSION
+		} else if spec.is_westend() {
+			&polkadot_service::westend_runtime::VERSION
+		} else {
+			&polkadot_service::rococo_runtime::VERSION
+		}
 	}
 }
@@ -0,0 +1,74 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Tracking of competing parachain heads.
+
+use pba_pvf::HeadData;
+use std::collections::HashMap;
+
+/// Records the parent-child relationships between the produced heads.
+pub(crate) struct ForkTree {
+	/// Hashes of the children of every known head, keyed by the hash of the head.
+	children: HashMap<[u8; 32], Vec<[u8; 32]>>,
+	/// The head of the longest chain, the first seen head winning ties.
+	best: HeadData,
+}
+
+impl ForkTree {
+	/// Create a tree with only the `genesis` head.
+	pub(crate) fn new(genesis: HeadData) -> Self {
+		Self { children: HashMap::new(), best: genesis }
+	}
+
+	/// Record `head` as a child of its parent.
+	pub(crate) fn insert(&mut self, head: &HeadData) {
+		let hash = head.hash();
+		let siblings = self.children.entry(head.parent_hash).or_default();
+		if siblings.contains(&hash) {
+			return
+		}
+		siblings.push(hash);
+
+		if head.number > self.best.number {
+			self.best = head.clone();
+		}
+	}
+
+	/// Forget `head`, along with the hashes of its children.
+	///
+	/// The best head must not be removed.
+	pub(crate) fn remove(&mut self, head: &HeadData) {
+		debug_assert!(head != &self.best, "the best head is never removed");
+		let hash = head.hash();
+		if let Some(siblings) = self.children.get_mut(&head.parent_hash) {
+			siblings.retain(|sibling| sibling != &hash);
+			if siblings.is_empty() {
+				self.children.remove(&head.parent_hash);
+			}
+		}
+		self.children.remove(&hash);
+	}
+
+	/// Get the head of the longest known chain.
+	pub(crate) fn best_head(&self) -> &HeadData {
+		&self.best
+	}
+
+	/// Get the hashes of the known children of the head with the given `hash`.
+	pub(crate) fn children(&self, hash: &[u8; 32]) -> &[[u8; 32]] {
+		self.children.get(hash).map(|c| &c[..]).unwrap_or(&[])
+	}
+}
@@ -0,0 +1,150 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Outbound HRMP messages of the collator.
+
+use crate::LOG_TARGET;
+use pba_pvf::HorizontalMessage;
+use polkadot_primitives::Id as ParaId;
+use std::{
+	collections::{BTreeMap, VecDeque},
+	fmt,
+};
+
+/// The maximum number of horizontal messages sent per collation.
+///
+/// Matches the default host configuration of the relay chain.
+pub const HRMP_MAX_MESSAGE_NUM_PER_CANDIDATE: usize = 5;
+
+/// Limits of an open HRMP channel, as configured on the relay chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HrmpChannelLimits {
+	/// The maximum size of a single message sent over the channel.
+	pub max_message_size: u32,
+}
+
+/// Errors that can occur while queueing a horizontal message.
+#[derive(Debug, PartialEq, Eq)]
+pub enum HorizontalMessageError {
+	/// There is no open channel to the recipient.
+	ChannelNotOpen(ParaId),
+	/// The message exceeds the maximum message size of the channel.
+	TooLarge { size: usize, max: u32 },
+}
+
+impl fmt::Display for HorizontalMessageError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::ChannelNotOpen(recipient) => write!(f, "no open HRMP channel to {}", recipient),
+			Self::TooLarge { size, max } =>
+				write!(f, "HRMP message of {} bytes exceeds the maximum of {} bytes", size, max),
+		}
+	}
+}
+
+impl std::error::Error for HorizontalMessageError {}
+
+/// The open channels and the messages waiting to be sent over them.
+#[derive(Default)]
+pub(crate) struct HrmpOutbox {
+	channels: BTreeMap<ParaId, HrmpChannelLimits>,
+	queue: VecDeque<(ParaId, Vec<u8>)>,
+}
+
+impl HrmpOutbox {
+	/// Open a channel to `recipient`, or update its limits if already open.
+	pub(crate) fn open_channel(&mut self, recipient: ParaId, limits: HrmpChannelLimits) {
+		self.channels.insert(recipient, limits);
+	}
+
+	/// Close the channel to `recipient`.
+	///
+	/// Messages still queued for it are dropped when the next collation is built.
+	pub(crate) fn close_channel(&mut self, recipient: ParaId) {
+		self.channels.remove(&recipient);
+	}
+
+	/// Queue a message to `recipient`.
+	pub(crate) fn enqueue(
+		&mut self,
+		recipient: ParaId,
+		data: Vec<u8>,
+	) -> Result<(), HorizontalMessageError> {
+		let limits = self
+			.channels
+			.get(&recipient)
+			.ok_or(HorizontalMessageError::ChannelNotOpen(recipient))?;
+		if data.len() > limits.max_message_size as usize {
+			return Err(HorizontalMessageError::TooLarge {
+				size: data.len(),
+				max: limits.max_message_size,
+			})
+		}
+
+		self.queue.push_back((recipient, data));
+		Ok(())
+	}
+
+	/// Select the messages to send with the next collation, without dequeueing them.
+	///
+	/// The relay chain accepts at most one message per recipient per candidate, sorted by
+	/// recipient. Messages to channels that are no longer open or whose limits shrank are dropped.
+	pub(crate) fn select(&mut self) -> Vec<HorizontalMessage> {
+		let channels = &self.channels;
+		self.queue.retain(|(recipient, data)| match channels.get(recipient) {
+			None => {
+				log::warn!(
+					target: LOG_TARGET,
+					"Dropping HRMP message to {}: channel is not open",
+					recipient,
+				);
+				false
+			},
+			Some(limits) if data.len() > limits.max_message_size as usize => {
+				log::warn!(
+					target: LOG_TARGET,
+					"Dropping HRMP message to {}: exceeds the channel limits",
+					recipient,
+				);
+				false
+			},
+			Some(_) => true,
+		});
+
+		let mut selected = BTreeMap::new();
+		for (recipient, data) in &self.queue {
+			if selected.len() == HRMP_MAX_MESSAGE_NUM_PER_CANDIDATE {
+				break
+			}
+			selected.entry(*recipient).or_insert_with(|| data.clone());
+		}
+
+		selected
+			.into_iter()
+			.map(|(recipient, data)| HorizontalMessage { recipient: recipient.into(), data })
+			.collect()
+	}
+
+	/// Dequeue the messages previously returned by [`Self::select`].
+	pub(crate) fn remove_sent(&mut self, sent: &[HorizontalMessage]) {
+		for msg in sent {
+			let recipient = ParaId::from(msg.recipient);
+			if let Some(pos) = self.queue.iter().position(|(r, _)| *r == recipient) {
+				self.queue.remove(pos);
+			}
+		}
+	}
+}
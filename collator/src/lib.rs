@@ -16,74 +16,1374 @@
 
 //! Collator for the PBA parachain.
 
-use futures::channel::oneshot;
+mod fork_tree;
+mod hrmp;
+pub mod metrics;
+pub mod rpc;
+
+use fork_tree::ForkTree;
+use futures::{
+	channel::{mpsc, oneshot},
+	executor::block_on,
+	future::{self, Either},
+	lock::Mutex as AsyncMutex,
+};
+use hrmp::HrmpOutbox;
+pub use hrmp::{HorizontalMessageError, HrmpChannelLimits, HRMP_MAX_MESSAGE_NUM_PER_CANDIDATE};
+use metrics::Metrics;
 use parity_scale_codec::{Decode, Encode};
-use pba_pvf::{execute, hash_state, BlockData, HeadData};
+use pba_pvf::{
+	execute_with_events, BlockData, Event, ExecuteError, HeadData, HorizontalMessage, Op,
+	VersionedHeadData,
+};
+pub use pba_pvf::{ArithmeticMode, HashMode, StateCommitment};
 use polkadot_node_primitives::{
 	Collation, CollationResult, CollationSecondedSignal, CollatorFn, MaybeCompressedPoV, PoV,
-	Statement,
+	Statement, POV_BOMB_LIMIT,
+};
+use polkadot_primitives::{
+	BlockNumber as RelayChainBlockNumber, CollatorId, CollatorPair, Hash, Id as ParaId,
+	InboundDownwardMessage, OutboundHrmpMessage, ValidationCode, ValidationCodeHash, MAX_POV_SIZE,
 };
-use polkadot_primitives::{CollatorId, CollatorPair};
-use sp_core::{traits::SpawnNamed, Pair};
+use prometheus_endpoint::{PrometheusError, Registry};
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use sp_core::{crypto::SecretStringError, hexdisplay::HexDisplay, traits::SpawnNamed, Pair};
 use std::{
-	collections::HashMap,
-	sync::{Arc, Mutex},
+	collections::VecDeque,
+	fmt, io,
+	path::Path,
+	sync::{
+		atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+		Arc, Mutex, MutexGuard,
+	},
+	time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-/// The amount we add when producing a new block.
-const ADD: u64 = 7;
+/// Target of all the logs of the collator.
+pub const LOG_TARGET: &str = "pba-collator";
+
+/// The parachain id of a collator unless configured otherwise, see [`CollatorConfig::para_id`].
+pub const DEFAULT_PARA_ID: ParaId = ParaId::new(100);
+
+/// The default amount we add when producing a new block.
+pub const DEFAULT_ADD: u64 = 7;
+
+/// Environment variable with the amount to add, if not configured otherwise, see [`resolve_add`].
+pub const ADD_ENV_VAR: &str = "PBA_ADD";
+
+/// The default numeric state of the genesis block.
+pub const DEFAULT_GENESIS_VALUE: u64 = 0;
+
+/// The maximum number of upward messages sent per collation.
+///
+/// Matches the default host configuration of the relay chain.
+pub const MAX_UPWARD_MESSAGE_NUM_PER_CANDIDATE: usize = 5;
+
+/// The maximum size of a single upward message.
+///
+/// Matches the default host configuration of the relay chain.
+pub const MAX_UPWARD_MESSAGE_SIZE: usize = 50 * 1024;
+
+/// How long to wait for a collation to be seconded by default.
+pub const DEFAULT_SECONDED_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Number of collations awaiting a seconded statement above which a warning is logged by default.
+pub const DEFAULT_PENDING_SECONDED_THRESHOLD: usize = 16;
+
+/// Lock `mutex`, recovering the guard if a previous holder panicked.
+///
+/// The queues guarded this way stay consistent at every point a panic could occur, so a single
+/// panic shouldn't stop collation production for good.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+	mutex.lock().unwrap_or_else(|poisoned| {
+		log::warn!(target: LOG_TARGET, "recovering from a poisoned mutex");
+		poisoned.into_inner()
+	})
+}
+
+/// Picks the operation of a new block from its number, see [`Collator::with_operation_fn`].
+pub type OperationFn = Arc<dyn Fn(u64) -> Op + Send + Sync>;
+
+/// Configuration of the genesis of the parachain.
+///
+/// All collators of a chain must agree on it, everything but the amount to add makes up the
+/// genesis head registered on the relay chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GenesisConfig {
+	/// The numeric state of the genesis block.
+	pub state: u64,
+	/// The amount added to the state by every produced block.
+	///
+	/// If unset, see [`GenesisConfig::resolve_add`].
+	pub add: Option<u64>,
+	/// How the operations of the chain behave on overflow.
+	pub mode: ArithmeticMode,
+	/// The hash function of the heads and states of the chain.
+	pub hash_mode: HashMode,
+	/// Whether the heads only commit to the state, or also to all the operations applied.
+	pub commitment: StateCommitment,
+}
+
+impl Default for GenesisConfig {
+	fn default() -> Self {
+		Self {
+			state: DEFAULT_GENESIS_VALUE,
+			add: None,
+			mode: ArithmeticMode::default(),
+			hash_mode: HashMode::default(),
+			commitment: StateCommitment::default(),
+		}
+	}
+}
+
+impl GenesisConfig {
+	/// Get the genesis head of the chain.
+	pub fn genesis_head(&self) -> HeadData {
+		pba_pvf::genesis_head_with(self.state, self.mode, self.hash_mode, self.commitment)
+	}
+
+	/// Get the amount to add, unless overridden by `cli`, taking [`ADD_ENV_VAR`] into account.
+	pub fn resolve_add(&self, cli: Option<u64>) -> u64 {
+		resolve_add(cli, self.add, std::env::var(ADD_ENV_VAR).ok().as_deref())
+	}
+}
+
+/// Resolve the amount to add from the command line, the configuration and the value of
+/// [`ADD_ENV_VAR`] in that order of precedence, falling back to [`DEFAULT_ADD`].
+///
+/// An `env` value that isn't a number is ignored.
+pub fn resolve_add(cli: Option<u64>, config: Option<u64>, env: Option<&str>) -> u64 {
+	let env = env.and_then(|env| match env.parse() {
+		Ok(add) => Some(add),
+		Err(e) => {
+			log::warn!(target: LOG_TARGET, "ignoring {}={:?}: {}", ADD_ENV_VAR, env, e);
+			None
+		},
+	});
+
+	cli.or(config).or(env).unwrap_or(DEFAULT_ADD)
+}
+
+/// Errors that can occur while advancing the state.
+#[derive(Debug)]
+enum AdvanceError {
+	/// The parent head is not known, e.g. after a relay-chain reorg.
+	UnknownParent,
+	/// Executing the new block failed.
+	Execute(ExecuteError),
+}
+
+impl From<ExecuteError> for AdvanceError {
+	fn from(e: ExecuteError) -> Self {
+		Self::Execute(e)
+	}
+}
+
+/// Why the collation function didn't produce a collation.
+#[derive(Debug)]
+pub enum CollationError {
+	/// The parent head of `len` bytes in the validation data doesn't decode.
+	DecodeParent {
+		/// Length of the parent head, in bytes.
+		len: usize,
+		/// Why decoding failed.
+		error: parity_scale_codec::Error,
+	},
+	/// The SCALE encoded parent head is not known, e.g. after a relay-chain reorg.
+	UnknownParent(Vec<u8>),
+	/// The PoV is larger than the maximum the collator hands out.
+	OversizedPov {
+		/// Size of the PoV as sent to the validators, in bytes.
+		size: usize,
+		/// The maximum size, see [`Collator::set_max_pov_size`].
+		max_pov_size: u32,
+	},
+	/// The block can't be produced on top of its parent.
+	StateError(ExecuteError),
+	/// The block with the given number doesn't pass the validation logic.
+	Verify(u64, VerifyError),
+}
+
+impl CollationError {
+	/// Log why no collation was produced on `relay_parent`.
+	fn log(&self, relay_parent: Hash) {
+		let level = match self {
+			Self::UnknownParent(_) => log::Level::Warn,
+			_ => log::Level::Error,
+		};
+		log::log!(
+			target: LOG_TARGET,
+			level,
+			"skipping collation on relay-parent({}): {}",
+			relay_parent,
+			self,
+		);
+	}
+}
+
+impl fmt::Display for CollationError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		// Start with the variant name, so that every kind of failure is easy to grep for.
+		match self {
+			Self::DecodeParent { len, error } =>
+				write!(f, "DecodeParent: invalid parent head of {} bytes: {}", len, error),
+			Self::UnknownParent(head) => write!(f, "UnknownParent: 0x{:?}", HexDisplay::from(head)),
+			Self::OversizedPov { size, max_pov_size } => write!(
+				f,
+				"OversizedPov: PoV of {} bytes exceeds the maximum of {} bytes",
+				size, max_pov_size,
+			),
+			Self::StateError(e) => write!(f, "StateError: {:?}", e),
+			Self::Verify(number, e) =>
+				write!(f, "Verify: block #{} doesn't pass the validation logic: {:?}", number, e,),
+		}
+	}
+}
+
+impl std::error::Error for CollationError {}
+
+/// Decode the SCALE encoded `parent_head` of the validation data of a collation.
+///
+/// The head is kept in its own version, whose hash its child refers to.
+pub fn decode_parent_head(parent_head: &[u8]) -> Result<VersionedHeadData, CollationError> {
+	VersionedHeadData::decode(&mut &parent_head[..])
+		.map_err(|error| CollationError::DecodeParent { len: parent_head.len(), error })
+}
+
+/// Errors that can occur while loading the collator key.
+#[derive(Debug)]
+pub enum KeyError {
+	/// The key file could not be read.
+	Io(io::Error),
+	/// The seed could not be turned into a [`CollatorPair`].
+	InvalidSeed(SecretStringError),
+}
+
+impl fmt::Display for KeyError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Io(e) => write!(f, "failed to read the collator key file: {}", e),
+			Self::InvalidSeed(e) => write!(f, "invalid collator seed: {:?}", e),
+		}
+	}
+}
+
+impl std::error::Error for KeyError {}
+
+/// Build the collator key from a seed, i.e. a secret phrase, a hex seed or a secret URI.
+pub fn collator_key_from_seed(seed: &str) -> Result<CollatorPair, KeyError> {
+	CollatorPair::from_string(seed, None).map_err(KeyError::InvalidSeed)
+}
+
+/// Build the collator key from the seed stored in the file at `path`.
+pub fn collator_key_from_file(path: impl AsRef<Path>) -> Result<CollatorPair, KeyError> {
+	let seed = std::fs::read_to_string(path).map_err(KeyError::Io)?;
+	collator_key_from_seed(seed.trim())
+}
+
+/// An upward message exceeding [`MAX_UPWARD_MESSAGE_SIZE`].
+#[derive(Debug)]
+pub struct UpwardMessageTooLarge(pub usize);
+
+impl fmt::Display for UpwardMessageTooLarge {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"upward message of {} bytes exceeds the maximum of {} bytes",
+			self.0, MAX_UPWARD_MESSAGE_SIZE,
+		)
+	}
+}
+
+impl std::error::Error for UpwardMessageTooLarge {}
+
+/// What to do when the relay chain seconds a statement that doesn't match our collation.
+///
+/// The mismatch is logged either way, and only the result of the affected collation is dropped.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SecondedMismatchPolicy {
+	/// Stop producing collations until [`Collator::resume`] is called.
+	Strict,
+	/// Keep producing collations.
+	#[default]
+	Lenient,
+}
+
+/// How the collator compresses the PoV of its collations.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PovCompression {
+	/// Hand out the PoV raw, skipping compression, e.g. to inspect its bytes.
+	///
+	/// The PoV is still handed out as compressed, so that the collation generation subsystem
+	/// doesn't compress it, and seconded statements are checked against the hash of the raw PoV.
+	None,
+	/// Compress with zstd at the level of `maybe_compress_pov`.
+	#[default]
+	Default,
+	/// Compress with zstd at the given level.
+	///
+	/// Higher levels yield smaller PoVs, but take longer to compress.
+	Zstd(i32),
+}
+
+/// Prefix marking a zstd compressed blob, the same as the one of `sp_maybe_compressed_blob`,
+/// which doesn't expose it.
+const ZSTD_PREFIX: [u8; 8] = [82, 188, 83, 118, 70, 219, 142, 5];
+
+/// Compress `data` with zstd at the given `level` the way `sp_maybe_compressed_blob` does, so that
+/// validators can decompress it.
+///
+/// Returns `None` if `data` is too large to be decompressed again.
+fn compress_with_level(data: &[u8], level: i32) -> Option<Vec<u8>> {
+	if data.len() > POV_BOMB_LIMIT {
+		return None
+	}
+
+	let mut compressed = ZSTD_PREFIX.to_vec();
+	zstd::stream::copy_encode(data, &mut compressed, level).ok()?;
+	Some(compressed)
+}
+
+/// How much the collator logs about every collation it produces.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CollationLogVerbosity {
+	/// Don't log the produced collations.
+	Off,
+	/// Log the number of the block and how it changed the state.
+	#[default]
+	Summary,
+	/// Also log the whole block data.
+	Full,
+}
+
+/// A seconded statement that doesn't match the collation it was sent for.
+#[derive(Debug)]
+struct SecondedMismatch(Statement);
+
+/// Wait for the seconded signal of a collation and check that it matches the `pov_hash`.
+///
+/// Returns `Ok(())` if the collation was not seconded at all, or not within `timeout`.
+async fn wait_for_seconded(
+	recv: oneshot::Receiver<CollationSecondedSignal>,
+	pov_hash: Hash,
+	timeout: Duration,
+) -> Result<(), SecondedMismatch> {
+	let res = match future::select(recv, futures_timer::Delay::new(timeout)).await {
+		Either::Left((res, _)) => res,
+		Either::Right(_) => {
+			tracing::warn!(
+				target: LOG_TARGET,
+				"Our collation was not seconded within {:?}",
+				timeout,
+			);
+			return Ok(())
+		},
+	};
+
+	if let Ok(res) = res {
+		if !matches!(
+			res.statement.payload(),
+			Statement::Seconded(s) if s.descriptor.pov_hash == pov_hash,
+		) {
+			return Err(SecondedMismatch(res.statement.payload().clone()))
+		}
+
+		tracing::info!(target: LOG_TARGET, "Our collation was seconded! {:?}", res);
+	}
+
+	Ok(())
+}
+
+/// A produced block that the validation logic disagrees with.
+#[derive(Debug)]
+pub enum VerifyError {
+	/// The block was rejected.
+	Execute(ExecuteError),
+	/// The block yields another head than the produced one.
+	HeadMismatch(HeadData),
+	/// The produced head doesn't commit to the state stored for it.
+	StateMismatch,
+}
+
+/// Re-execute `block_data` on top of the SCALE encoded `parent_head` the way validators do, and
+/// check that it yields `head`, whose state we stored as `state`.
+fn verify_block(
+	parent_head: &[u8],
+	block_data: &BlockData,
+	head: &HeadData,
+	state: u64,
+) -> Result<(), VerifyError> {
+	let parent = VersionedHeadData::decode(&mut &parent_head[..])
+		.expect("The parent head was decoded before producing the block");
+	// Validators hash the head as stored by the relay chain, rather than the migrated head.
+	let parent_hash = parent.hash_mode().hash(parent_head);
+	let block = BlockData::decode(&mut &block_data.encode()[..])
+		.expect("A block decodes from its own encoding");
+
+	let expected = pba_pvf::execute(parent_hash, parent, &block).map_err(VerifyError::Execute)?;
+	if &expected != head {
+		return Err(VerifyError::HeadMismatch(expected))
+	}
+	if !head.commits_to(state, block.mode) {
+		return Err(VerifyError::StateMismatch)
+	}
+
+	Ok(())
+}
+
+/// Size of the PoV of a collation, see [`build_collation`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PovSize {
+	/// Size of the uncompressed PoV, in bytes.
+	pub raw: usize,
+	/// Size of the compressed PoV, in bytes, or `None` if the PoV is too large to be compressed
+	/// and is sent as is.
+	pub compressed: Option<usize>,
+}
+
+impl PovSize {
+	/// Size of the PoV as sent to the validators, in bytes.
+	pub fn sent(&self) -> usize {
+		self.compressed.unwrap_or(self.raw)
+	}
+
+	/// Size of the sent PoV relative to the uncompressed one.
+	pub fn ratio(&self) -> f64 {
+		self.sent() as f64 / self.raw as f64
+	}
+}
+
+impl fmt::Display for PovSize {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.compressed {
+			Some(compressed) => write!(
+				f,
+				"{} bytes compressed to {} ({:.1}%)",
+				self.raw,
+				compressed,
+				self.ratio() * 100.0,
+			),
+			None => write!(f, "{} bytes uncompressed", self.raw),
+		}
+	}
+}
+
+/// Build the collation of the block with the given `block_data` and resulting `head_data`.
+///
+/// The PoV of the collation is compressed as given by `compression`. Returns the collation along
+/// with the hash of its PoV, which a seconded statement for it refers to, and the size of the
+/// PoV.
+pub fn build_collation(
+	block_data: &BlockData,
+	head_data: &HeadData,
+	relay_parent_number: RelayChainBlockNumber,
+	compression: PovCompression,
+) -> (Collation, Hash, PovSize) {
+	let raw = PoV { block_data: block_data.encode().into() };
+	let compressed = match compression {
+		PovCompression::None => None,
+		// Same as `maybe_compress_pov`, but tells whether the PoV was compressed.
+		PovCompression::Default =>
+			sp_maybe_compressed_blob::compress(&raw.block_data.0, POV_BOMB_LIMIT),
+		PovCompression::Zstd(level) => compress_with_level(&raw.block_data.0, level),
+	}
+	.map(|data| PoV { block_data: data.into() });
+	let pov_size = PovSize {
+		raw: raw.block_data.0.len(),
+		compressed: compressed.as_ref().map(|pov| pov.block_data.0.len()),
+	};
+
+	// Compress once up front, the collation generation subsystem doesn't compress an already
+	// compressed PoV again and hashes exactly what we pass it. That also holds for a raw PoV we
+	// pass as compressed, whereas it would compress one passed as raw.
+	let pov = compressed.unwrap_or(raw);
+	let pov_hash = pov.hash();
+	let pov = MaybeCompressedPoV::Compressed(pov);
+
+	let collation = Collation {
+		upward_messages: block_data.upward_messages.clone(),
+		horizontal_messages: block_data
+			.horizontal_messages
+			.iter()
+			.map(|msg| OutboundHrmpMessage {
+				recipient: ParaId::from(msg.recipient),
+				data: msg.data.clone(),
+			})
+			.collect(),
+		new_validation_code: block_data.new_validation_code.clone().map(ValidationCode),
+		head_data: head_data.encode().into(),
+		proof_of_validity: pov,
+		processed_downward_messages: block_data.processed_downward_messages,
+		// We don't process any inbound HRMP messages, so the watermark simply follows the relay
+		// parent. That includes the relay chain genesis: the watermark may not exceed the relay
+		// parent number, so zero is the only valid watermark there.
+		hrmp_watermark: relay_parent_number,
+	};
+
+	(collation, pov_hash, pov_size)
+}
+
+/// Accounts for a collation awaiting a seconded statement until dropped.
+struct PendingSeconded(Arc<AtomicUsize>);
+
+impl PendingSeconded {
+	fn new(counter: Arc<AtomicUsize>) -> Self {
+		counter.fetch_add(1, Ordering::Relaxed);
+		Self(counter)
+	}
+
+	/// Number of collations awaiting a seconded statement, including this one.
+	fn count(&self) -> usize {
+		self.0.load(Ordering::Relaxed)
+	}
+}
+
+impl Drop for PendingSeconded {
+	fn drop(&mut self) {
+		self.0.fetch_sub(1, Ordering::Relaxed);
+	}
+}
+
+/// Milliseconds since the Unix epoch.
+fn now_millis() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+/// A collation produced by the collator.
+#[derive(Clone, Debug)]
+pub struct ProducedCollation {
+	/// Hash of the new head.
+	pub head_hash: [u8; 32],
+	/// Number of the new block.
+	pub number: u64,
+	/// Size of the uncompressed PoV, in bytes.
+	pub pov_size: usize,
+	/// How the block changed the state.
+	pub diff: StateDiff,
+}
+
+/// Statistics about the collations produced by a collator, see [`Collator::stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CollationStats {
+	/// Number of collations produced.
+	pub produced: u64,
+	/// Total size of the uncompressed PoVs of these collations, in bytes.
+	pub total_pov_bytes: u64,
+}
+
+impl CollationStats {
+	/// Average size of the uncompressed PoVs, in bytes, or `None` if nothing was produced yet.
+	pub fn average_pov_bytes(&self) -> Option<u64> {
+		self.total_pov_bytes.checked_div(self.produced)
+	}
+}
+
+/// The counters behind [`CollationStats`].
+#[derive(Default)]
+struct StatsCounters {
+	produced: AtomicU64,
+	total_pov_bytes: AtomicU64,
+}
+
+impl StatsCounters {
+	fn on_collation_produced(&self, pov_size: usize) {
+		self.produced.fetch_add(1, Ordering::Relaxed);
+		self.total_pov_bytes.fetch_add(pov_size as u64, Ordering::Relaxed);
+	}
+
+	/// The two counters are read separately, so a collation produced meanwhile might only be
+	/// accounted for in one of them.
+	fn get(&self) -> CollationStats {
+		CollationStats {
+			produced: self.produced.load(Ordering::Relaxed),
+			total_pov_bytes: self.total_pov_bytes.load(Ordering::Relaxed),
+		}
+	}
+}
+
+/// How a block changed the numeric state of the parachain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StateDiff {
+	/// The state of the parent block.
+	pub before: u64,
+	/// The state after the block.
+	pub after: u64,
+}
+
+impl fmt::Display for StateDiff {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} -> {}", self.before, self.after)
+	}
+}
+
+/// A copy of all the heads known to a collator, see [`Collator::snapshot`].
+#[derive(Clone, Debug, Default, Encode, Decode, PartialEq, Eq)]
+pub struct StateSnapshot {
+	/// The known heads along with the state after them, sorted by block number.
+	pub entries: Vec<(HeadData, u64)>,
+}
+
+/// Messages sent and received by a block.
+#[derive(Default)]
+struct BlockMessages {
+	/// Messages sent to the relay chain.
+	upward: Vec<Vec<u8>>,
+	/// Messages sent to other parachains.
+	horizontal: Vec<HorizontalMessage>,
+	/// Number of downward messages processed.
+	processed_downward: u32,
+	/// New validation code to schedule.
+	new_validation_code: Option<Vec<u8>>,
+}
+
+/// A block produced by [`StateDb::advance`].
+struct Advanced {
+	/// The block data of the new block.
+	block_data: BlockData,
+	/// The head of the new block.
+	head: HeadData,
+	/// How the new block changed the state.
+	diff: StateDiff,
+	/// The events emitted by the new block.
+	events: Vec<Event>,
+	/// The exact same block was produced before, e.g. for another collation on the same parent.
+	///
+	/// A block produced again on the same parent takes over the timestamp of the first one.
+	duplicate: bool,
+}
 
 /// The state of the parachain.
 struct StateDb {
 	/// In real world, this is handled by the blockchain database.
-	head_to_state: HashMap<HeadData, u64>,
+	///
+	/// Uses the non DoS-resistant Fx hasher: the heads are produced by ourselves or commit to
+	/// hashes anyway.
+	head_to_state: FxHashMap<HeadData, u64>,
+	/// The heads of `head_to_state` by their hash.
+	hash_to_head: FxHashMap<[u8; 32], HeadData>,
+	/// The head of the genesis block.
+	genesis_head: HeadData,
+	/// Parent-child relationships between the known heads.
+	fork_tree: ForkTree,
+	/// The maximum number of heads kept in `head_to_state`, if bounded.
+	capacity: Option<usize>,
+	/// When each head in `head_to_state` was last advanced, used for LRU eviction.
+	last_advanced: FxHashMap<HeadData, u64>,
+	/// Incremented on every advance.
+	tick: u64,
+	/// The amount we add when producing a new block.
+	add: u64,
+	/// Overrides the constant add, if set.
+	operation: Option<OperationFn>,
+	/// How the operations of the chain behave on overflow, fixed at genesis.
+	mode: ArithmeticMode,
+	/// On-disk mirror of `head_to_state`, if the state is persisted.
+	#[cfg(feature = "persistence")]
+	db: Option<sled::Db>,
 }
 
 impl StateDb {
-	/// Init the genesis state.
-	fn genesis() -> Self {
-		let genesis_state =
-			HeadData { number: 0, parent_hash: Default::default(), post_state: hash_state(0) };
+	/// Init the genesis state with the given `genesis` configuration.
+	fn genesis(genesis: &GenesisConfig) -> Self {
+		let genesis_head = genesis.genesis_head();
 
-		let mut map = HashMap::new();
-		map.insert(genesis_state, 0);
+		let mut map = FxHashMap::default();
+		map.insert(genesis_head.clone(), genesis.state);
+		let mut hash_to_head = FxHashMap::default();
+		hash_to_head.insert(genesis_head.hash(), genesis_head.clone());
 
-		Self { head_to_state: map }
+		Self {
+			head_to_state: map,
+			hash_to_head,
+			fork_tree: ForkTree::new(genesis_head.clone()),
+			capacity: None,
+			last_advanced: FxHashMap::default(),
+			tick: 0,
+			genesis_head,
+			add: genesis.resolve_add(None),
+			operation: None,
+			mode: genesis.mode,
+			#[cfg(feature = "persistence")]
+			db: None,
+		}
 	}
 
-	/// Advance the state and produce a new block based on the given `parent_head`.
+	/// Open the state persisted at `path`.
 	///
-	/// Returns the new [`BlockData`] and the new [`HeadData`].
-	fn advance(&mut self, parent_head: HeadData) -> (BlockData, HeadData) {
-		let block = BlockData {
-			state: self.head_to_state.get(&parent_head).copied().expect("unknown parent head"),
-			add: ADD,
+	/// All previously produced heads are loaded into memory. If the database is empty, it is
+	/// initialized with the genesis state.
+	#[cfg(feature = "persistence")]
+	fn open(path: &Path, genesis: &GenesisConfig) -> sled::Result<Self> {
+		let db = sled::open(path)?;
+		let mut state = Self::genesis(genesis);
+
+		if db.is_empty() {
+			for (head, value) in &state.head_to_state {
+				db.insert(head.encode(), value.encode())?;
+			}
+			db.flush()?;
+		} else {
+			let mut heads = Vec::new();
+			for entry in db.iter() {
+				let (key, value) = entry?;
+				let decode_err = |e: parity_scale_codec::Error| {
+					sled::Error::Unsupported(format!("corrupted state entry: {}", e))
+				};
+
+				let head = HeadData::decode(&mut &key[..]).map_err(decode_err)?;
+				let value = u64::decode(&mut &value[..]).map_err(decode_err)?;
+				heads.push(head.clone());
+				state.head_to_state.insert(head, value);
+			}
+
+			state.rebuild_indexes(heads);
+		}
+
+		state.db = Some(db);
+		Ok(state)
+	}
+
+	/// Rebuild the fork tree and the hash index from scratch out of the given `heads`.
+	fn rebuild_indexes(&mut self, mut heads: Vec<HeadData>) {
+		self.fork_tree = ForkTree::new(self.genesis_head.clone());
+		self.hash_to_head = heads.iter().map(|head| (head.hash(), head.clone())).collect();
+		self.hash_to_head.insert(self.genesis_head.hash(), self.genesis_head.clone());
+
+		// Insert parents before their children, so that ties resolve the same as before.
+		heads.sort_by_key(|head| head.number);
+		for head in heads.iter().filter(|head| **head != self.genesis_head) {
+			self.fork_tree.insert(head);
+		}
+	}
+
+	/// Capture all the known heads and their states.
+	fn snapshot(&self) -> StateSnapshot {
+		let mut entries = self
+			.head_to_state
+			.iter()
+			.map(|(head, state)| (head.clone(), *state))
+			.collect::<Vec<_>>();
+		entries.sort_by_key(|(head, _)| head.number);
+		StateSnapshot { entries }
+	}
+
+	/// Replace all the known heads and their states with the ones of `snapshot`.
+	///
+	/// The genesis is kept, even if the snapshot doesn't contain it.
+	fn restore(&mut self, snapshot: StateSnapshot) {
+		let genesis_state = self.head_to_state[&self.genesis_head];
+		self.head_to_state = snapshot.entries.into_iter().collect();
+		self.head_to_state.insert(self.genesis_head.clone(), genesis_state);
+		self.last_advanced.clear();
+		self.rebuild_indexes(self.head_to_state.keys().cloned().collect());
+		self.prune();
+
+		#[cfg(feature = "persistence")]
+		self.persist_all();
+	}
+
+	/// Replace the state on disk with `head_to_state`, if the state is persisted.
+	#[cfg(feature = "persistence")]
+	fn persist_all(&self) {
+		if let Some(db) = &self.db {
+			let result = db.clear().and_then(|()| {
+				for (head, state) in &self.head_to_state {
+					db.insert(head.encode(), state.encode())?;
+				}
+				Ok(())
+			});
+			if let Err(e) = result {
+				log::error!(target: LOG_TARGET, "failed to persist the state: {}", e);
+			}
+		}
+	}
+
+	/// Write a single `head -> state` mapping to disk, if the state is persisted.
+	#[cfg(feature = "persistence")]
+	fn persist(&self, head: &HeadData, state: u64) {
+		if let Some(db) = &self.db {
+			if let Err(e) = db.insert(head.encode(), state.encode()) {
+				log::error!(target: LOG_TARGET, "failed to persist the state of {:?}: {}", head, e);
+			}
+		}
+	}
+
+	/// Remove the state of `head` from disk, if the state is persisted.
+	#[cfg(feature = "persistence")]
+	fn unpersist(&self, head: &HeadData) {
+		if let Some(db) = &self.db {
+			if let Err(e) = db.remove(head.encode()) {
+				log::error!(target: LOG_TARGET, "failed to remove the state of {:?}: {}", head, e);
+			}
+		}
+	}
+
+	/// Walk the chain of the best head back to genesis, starting with the best head.
+	///
+	/// Ends early at an ancestor whose state was evicted, see [`Self::prune`].
+	fn canonical_chain(&self) -> impl Iterator<Item = HeadData> + '_ {
+		std::iter::successors(Some(self.fork_tree.best_head().clone()), move |head| {
+			if *head == self.genesis_head {
+				return None
+			}
+			self.head_by_hash(&head.parent_hash).cloned()
+		})
+	}
+
+	/// Get the known head with the given `hash`.
+	fn head_by_hash(&self, hash: &[u8; 32]) -> Option<&HeadData> {
+		self.hash_to_head.get(hash)
+	}
+
+	/// Mark `head` as just advanced.
+	fn touch(&mut self, head: &HeadData) {
+		self.tick += 1;
+		self.last_advanced.insert(head.clone(), self.tick);
+	}
+
+	/// Evict the least recently advanced heads until at most `capacity` heads are kept.
+	///
+	/// The genesis and the best head are never evicted.
+	fn prune(&mut self) {
+		let capacity = match self.capacity {
+			Some(capacity) => capacity,
+			None => return,
 		};
 
-		let new_head =
-			execute(parent_head.hash(), parent_head, &block).expect("Produces valid block");
+		while self.head_to_state.len() > capacity {
+			let genesis = &self.genesis_head;
+			let best = self.fork_tree.best_head();
+			let victim = self
+				.head_to_state
+				.keys()
+				.filter(|head| *head != genesis && *head != best)
+				.min_by_key(|head| self.last_advanced.get(*head).copied().unwrap_or(0))
+				.cloned();
 
-		self.head_to_state.insert(new_head.clone(), block.state.wrapping_add(ADD));
+			let victim = match victim {
+				Some(victim) => victim,
+				None => break,
+			};
+
+			log::debug!(target: LOG_TARGET, "evicting the state of head #{}", victim.number);
+			self.head_to_state.remove(&victim);
+			self.hash_to_head.remove(&victim.hash());
+			self.last_advanced.remove(&victim);
+			self.fork_tree.remove(&victim);
 
-		(block, new_head)
+			#[cfg(feature = "persistence")]
+			self.unpersist(&victim);
+		}
+	}
+
+	/// Produce the next block based on the given `parent`, of any version, without recording it.
+	///
+	/// The block sends the given `messages`.
+	fn peek_advance(
+		&self,
+		parent: VersionedHeadData,
+		messages: BlockMessages,
+	) -> Result<Advanced, AdvanceError> {
+		let parent_head = parent.clone().into_latest();
+		let mut block = BlockData {
+			state: self
+				.head_to_state
+				.get(&parent_head)
+				.copied()
+				.ok_or(AdvanceError::UnknownParent)?,
+			ops: vec![self.next_op(parent_head.number.saturating_add(1))],
+			upward_messages: messages.upward,
+			horizontal_messages: messages.horizontal,
+			processed_downward_messages: messages.processed_downward,
+			new_validation_code: messages.new_validation_code,
+			// Fall back to just after the parent if the clock is behind.
+			timestamp: now_millis().max(parent_head.timestamp.saturating_add(1)),
+			mode: self.mode,
+		};
+		// Producing again on the same parent only changes the timestamp, so take the one of an
+		// otherwise identical sibling rather than forking off a copy of it.
+		if let Some(timestamp) = self.sibling_timestamp(&parent, &block) {
+			block.timestamp = timestamp;
+		}
+
+		let (new_head, events) = execute_with_events(parent.hash(), parent, &block)?;
+		let new_state = block.apply()?;
+		let duplicate = self.head_to_state.get(&new_head) == Some(&new_state);
+
+		let diff = StateDiff { before: block.state, after: new_state };
+		Ok(Advanced { block_data: block, head: new_head, diff, events, duplicate })
+	}
+
+	/// The operation the block with the given `number` applies.
+	fn next_op(&self, number: u64) -> Op {
+		match &self.operation {
+			Some(operation) => operation(number),
+			None => Op::Add(self.add),
+		}
+	}
+
+	/// Get the timestamp of a known child of `parent` that is the same block as `block` apart from
+	/// its timestamp.
+	fn sibling_timestamp(&self, parent: &VersionedHeadData, block: &BlockData) -> Option<u64> {
+		let parent_hash = parent.hash();
+		self.fork_tree
+			.children(&parent_hash)
+			.iter()
+			.filter_map(|hash| self.head_by_hash(hash))
+			.find(|sibling| {
+				let block = BlockData { timestamp: sibling.timestamp, ..block.clone() };
+				execute_with_events(parent_hash, parent.clone(), &block)
+					.map_or(false, |(head, _)| &head == *sibling)
+			})
+			.map(|sibling| sibling.timestamp)
+	}
+
+	/// Advance the state and produce a new block based on the given `parent_head`.
+	///
+	/// Same as [`Self::peek_advance`], but records the new block, see [`Self::record`].
+	fn advance(
+		&mut self,
+		parent_head: HeadData,
+		messages: BlockMessages,
+	) -> Result<Advanced, AdvanceError> {
+		let advanced = self.peek_advance(parent_head.clone().into(), messages)?;
+		self.record(&parent_head, &advanced);
+		Ok(advanced)
+	}
+
+	/// Record the block produced on top of `parent_head` by [`Self::peek_advance`].
+	///
+	/// Producing the exact same block again doesn't insert it twice, see [`Advanced::duplicate`].
+	fn record(&mut self, parent_head: &HeadData, advanced: &Advanced) {
+		let (new_head, new_state) = (&advanced.head, advanced.diff.after);
+		for event in &advanced.events {
+			log::debug!(target: LOG_TARGET, "block #{}: {:?}", new_head.number, event);
+		}
+
+		self.touch(parent_head);
+		if advanced.duplicate {
+			log::debug!(
+				target: LOG_TARGET,
+				"block #{} was already produced, not inserting it again",
+				new_head.number,
+			);
+		} else {
+			self.head_to_state.insert(new_head.clone(), new_state);
+			self.hash_to_head.insert(new_head.hash(), new_head.clone());
+			self.fork_tree.insert(new_head);
+
+			#[cfg(feature = "persistence")]
+			self.persist(new_head, new_state);
+		}
+		self.touch(&advanced.head);
+		self.prune();
+	}
+
+	/// Advance the state `n` times, starting on top of `from_head` and then on top of every new
+	/// head, returning the last one.
+	///
+	/// All the intermediate heads are recorded. Fails if the parent of any block isn't known.
+	fn advance_n(&mut self, from_head: HeadData, n: u64) -> Result<HeadData, AdvanceError> {
+		(0..n).try_fold(from_head, |parent, _| {
+			self.advance(parent, BlockMessages::default()).map(|advanced| advanced.head)
+		})
 	}
 }
 
 /// The collator of the parachain.
 pub struct Collator {
-	state: Arc<Mutex<StateDb>>,
+	/// Locked from within the collation future and the RPC handlers, hence the async mutex, which
+	/// all the accessors await rather than block on. It isn't poisoned by a panicking holder,
+	/// unlike the blocking mutexes below.
+	state: Arc<AsyncMutex<StateDb>>,
+	/// The parachain we collate for.
+	para_id: ParaId,
+	/// The SCALE encoded genesis head, which never changes.
+	genesis_head: Vec<u8>,
 	key: CollatorPair,
+	metrics: Metrics,
+	stats: Arc<StatsCounters>,
+	seconded_mismatch: SecondedMismatchPolicy,
+	/// How long to wait for a collation to be seconded before giving up on it.
+	seconded_timeout: Duration,
+	/// Number of collations currently awaiting a seconded statement.
+	pending_seconded: Arc<AtomicUsize>,
+	/// More collations awaiting a seconded statement than this are warned about.
+	pending_seconded_threshold: usize,
+	/// Set once collation production was stopped due to a seconded mismatch.
+	halted: Arc<AtomicBool>,
+	/// Upward messages waiting to be included in a collation.
+	upward_messages: Arc<Mutex<VecDeque<Vec<u8>>>>,
+	/// Horizontal messages waiting to be included in a collation.
+	hrmp_outbox: Arc<Mutex<HrmpOutbox>>,
+	/// Downward messages waiting to be processed.
+	downward_messages: Arc<Mutex<VecDeque<InboundDownwardMessage>>>,
+	/// Validation code waiting to be sent with a collation.
+	new_validation_code: Arc<Mutex<Option<Vec<u8>>>>,
+	/// Collations with a larger compressed PoV are dropped.
+	max_pov_size: u32,
+	/// How to compress the PoV of the collations.
+	pov_compression: PovCompression,
+	/// Whether to re-execute every produced block before handing out its collation.
+	verify_collations: bool,
+	/// How much to log about every produced collation.
+	collation_log: CollationLogVerbosity,
+	/// The highest HRMP watermark of the collations produced so far.
+	last_hrmp_watermark: Arc<AtomicU32>,
+	/// Subscribers to the produced collations.
+	collation_listeners: Arc<Mutex<Vec<mpsc::UnboundedSender<ProducedCollation>>>>,
+}
+
+/// Configuration of a [`Collator`], see [`Collator::from_config`].
+#[derive(Clone)]
+pub struct CollatorConfig {
+	para_id: ParaId,
+	genesis: GenesisConfig,
+	key: Option<CollatorPair>,
+	pov_compression: PovCompression,
+	verify_collations: bool,
+	collation_log: CollationLogVerbosity,
+	max_pov_size: u32,
+	seconded_mismatch: SecondedMismatchPolicy,
+	seconded_timeout: Duration,
+	pending_seconded_threshold: usize,
+}
+
+impl Default for CollatorConfig {
+	fn default() -> Self {
+		Self {
+			para_id: DEFAULT_PARA_ID,
+			genesis: GenesisConfig::default(),
+			key: None,
+			pov_compression: PovCompression::default(),
+			verify_collations: false,
+			collation_log: CollationLogVerbosity::default(),
+			max_pov_size: MAX_POV_SIZE,
+			seconded_mismatch: SecondedMismatchPolicy::default(),
+			seconded_timeout: DEFAULT_SECONDED_TIMEOUT,
+			pending_seconded_threshold: DEFAULT_PENDING_SECONDED_THRESHOLD,
+		}
+	}
+}
+
+impl CollatorConfig {
+	/// Set the id of the parachain to collate for.
+	pub fn para_id(mut self, para_id: ParaId) -> Self {
+		self.para_id = para_id;
+		self
+	}
+
+	/// Set the genesis of the chain, which every collator of the chain must agree on.
+	pub fn genesis(mut self, genesis: GenesisConfig) -> Self {
+		self.genesis = genesis;
+		self
+	}
+
+	/// Use `key` as the collator key instead of a randomly generated one.
+	pub fn collator_key(mut self, key: CollatorPair) -> Self {
+		self.key = Some(key);
+		self
+	}
+
+	/// See [`Collator::set_pov_compression`].
+	pub fn pov_compression(mut self, compression: PovCompression) -> Self {
+		self.pov_compression = compression;
+		self
+	}
+
+	/// See [`Collator::set_verify_collations`].
+	pub fn verify_collations(mut self, verify: bool) -> Self {
+		self.verify_collations = verify;
+		self
+	}
+
+	/// See [`Collator::set_collation_log_verbosity`].
+	pub fn collation_log_verbosity(mut self, verbosity: CollationLogVerbosity) -> Self {
+		self.collation_log = verbosity;
+		self
+	}
+
+	/// See [`Collator::set_max_pov_size`].
+	pub fn max_pov_size(mut self, size: u32) -> Self {
+		self.max_pov_size = size;
+		self
+	}
+
+	/// See [`Collator::set_seconded_mismatch_policy`].
+	pub fn seconded_mismatch_policy(mut self, policy: SecondedMismatchPolicy) -> Self {
+		self.seconded_mismatch = policy;
+		self
+	}
+
+	/// See [`Collator::set_seconded_timeout`].
+	pub fn seconded_timeout(mut self, timeout: Duration) -> Self {
+		self.seconded_timeout = timeout;
+		self
+	}
+
+	/// See [`Collator::set_pending_seconded_threshold`].
+	pub fn pending_seconded_threshold(mut self, threshold: usize) -> Self {
+		self.pending_seconded_threshold = threshold;
+		self
+	}
+}
+
+impl Default for Collator {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 impl Collator {
-	/// Create a new collator instance with the state initialized as genesis.
+	/// Create a new collator instance with the default configuration.
 	pub fn new() -> Self {
-		Self { state: Arc::new(Mutex::new(StateDb::genesis())), key: CollatorPair::generate().0 }
+		Self::from_config(CollatorConfig::default())
+	}
+
+	/// Same as [`Self::new`], but the collator key is derived from `seed` instead of being random.
+	///
+	/// Meant for tests, which get the same [`Self::collator_id`] on every run.
+	pub fn new_with_rng_seed(seed: [u8; 32]) -> Self {
+		Self::from_config(CollatorConfig::default().collator_key(CollatorPair::from_seed(&seed)))
+	}
+
+	/// Create a new collator instance with the state initialized as the genesis of `config`.
+	pub fn from_config(config: CollatorConfig) -> Self {
+		Self::from_state_db(StateDb::genesis(&config.genesis), config)
+	}
+
+	/// Create a new collator instance of the chain of `config` that knows the heads of `snapshot`.
+	///
+	/// Builds a collator with a known history without producing it block by block, e.g. in tests.
+	/// The genesis of `config` is known in any case, see [`Self::restore`].
+	pub fn with_state(config: CollatorConfig, snapshot: StateSnapshot) -> Self {
+		let mut state = StateDb::genesis(&config.genesis);
+		state.restore(snapshot);
+		Self::from_state_db(state, config)
+	}
+
+	/// Create a new collator instance whose key is derived from `seed`.
+	///
+	/// See [`collator_key_from_seed`] for the accepted formats.
+	pub fn from_seed(seed: &str) -> Result<Self, KeyError> {
+		let mut collator = Self::default();
+		collator.set_collator_key(collator_key_from_seed(seed)?);
+		Ok(collator)
+	}
+
+	/// Create a new collator instance whose key is derived from the seed in the file at `path`.
+	pub fn from_key_file(path: impl AsRef<Path>) -> Result<Self, KeyError> {
+		let mut collator = Self::default();
+		collator.set_collator_key(collator_key_from_file(path)?);
+		Ok(collator)
+	}
+
+	/// Create a new collator instance with the state persisted at `path`.
+	///
+	/// If there is no state at `path` yet, it is initialized as the genesis of `config`.
+	#[cfg(feature = "persistence")]
+	pub fn open(config: CollatorConfig, path: impl AsRef<Path>) -> sled::Result<Self> {
+		let state = StateDb::open(path.as_ref(), &config.genesis)?;
+		Ok(Self::from_state_db(state, config))
+	}
+
+	/// Create a new collator instance on top of the given state.
+	fn from_state_db(state: StateDb, config: CollatorConfig) -> Self {
+		Self {
+			para_id: config.para_id,
+			genesis_head: state.genesis_head.encode(),
+			state: Arc::new(AsyncMutex::new(state)),
+			key: config.key.unwrap_or_else(|| CollatorPair::generate().0),
+			metrics: Metrics::default(),
+			stats: Default::default(),
+			seconded_mismatch: config.seconded_mismatch,
+			seconded_timeout: config.seconded_timeout,
+			pending_seconded: Default::default(),
+			pending_seconded_threshold: config.pending_seconded_threshold,
+			halted: Arc::new(AtomicBool::new(false)),
+			upward_messages: Default::default(),
+			hrmp_outbox: Default::default(),
+			downward_messages: Default::default(),
+			new_validation_code: Default::default(),
+			max_pov_size: config.max_pov_size,
+			pov_compression: config.pov_compression,
+			verify_collations: config.verify_collations,
+			collation_log: config.collation_log,
+			last_hrmp_watermark: Default::default(),
+			collation_listeners: Default::default(),
+		}
+	}
+
+	/// Get the id of the parachain this collator collates for.
+	pub fn para_id(&self) -> ParaId {
+		self.para_id
 	}
 
 	/// Get the SCALE encoded genesis head of the parachain.
-	pub fn genesis_head(&self) -> Vec<u8> {
-		StateDb::genesis().head_to_state.keys().next().unwrap().encode()
+	pub fn genesis_head(&self) -> &[u8] {
+		&self.genesis_head
+	}
+
+	/// Check that the validation code accepts a first block on top of the genesis head.
+	///
+	/// Fails if the genesis state or arithmetic mode doesn't match the genesis head, e.g. because
+	/// the configuration drifted, or if the configured operation is rejected, e.g. because it adds
+	/// more than [`pba_pvf::MAX_ADD_PER_BLOCK`]. The check mirrors what validators do with the
+	/// registered head.
+	pub async fn verify_genesis(&self) -> Result<(), ExecuteError> {
+		let state = self.state.lock().await;
+		let genesis = HeadData::decode(&mut &self.genesis_head[..])
+			.expect("The genesis head is encoded by ourselves");
+		let block = BlockData {
+			state: state.head_to_state.get(&state.genesis_head).copied().unwrap_or_default(),
+			ops: vec![state.next_op(genesis.number.saturating_add(1))],
+			timestamp: genesis.timestamp.saturating_add(1),
+			mode: state.mode,
+			..Default::default()
+		};
+
+		let parent_hash = genesis.hash_mode.hash(&self.genesis_head);
+		pba_pvf::execute(parent_hash, genesis, &block).map(|_| ())
+	}
+
+	/// Get the numeric state of the parachain after the block with the given `head`.
+	///
+	/// Returns `None` if the head is not known to this collator.
+	pub async fn state_for(&self, head: &HeadData) -> Option<u64> {
+		self.state.lock().await.head_to_state.get(head).copied()
+	}
+
+	/// Get the numeric state of the parachain after the head with the given `hash`.
+	///
+	/// Returns `None` if the head is not known to this collator.
+	pub async fn state_at(&self, hash: &[u8; 32]) -> Option<u64> {
+		let state = self.state.lock().await;
+		let head = state.head_by_hash(hash)?;
+		state.head_to_state.get(head).copied()
+	}
+
+	/// Get the head with the given `hash`.
+	///
+	/// Returns `None` if the head is not known to this collator.
+	pub async fn head_by_hash(&self, hash: &[u8; 32]) -> Option<HeadData> {
+		self.state.lock().await.head_by_hash(hash).cloned()
+	}
+
+	/// Compute the block that would be produced on top of `parent_head` and its head, without
+	/// recording them.
+	///
+	/// Returns `None` if the parent is not known or the block can't be produced on top of it.
+	pub async fn peek_advance(&self, parent_head: HeadData) -> Option<(BlockData, HeadData)> {
+		let state = self.state.lock().await;
+		match state.peek_advance(parent_head.into(), BlockMessages::default()) {
+			Ok(Advanced { block_data, head, .. }) => Some((block_data, head)),
+			Err(e) => {
+				log::debug!(target: LOG_TARGET, "can't preview a block: {:?}", e);
+				None
+			},
+		}
+	}
+
+	/// Produce `n` blocks in a row on top of `from_head`, recording all of them, and return the
+	/// last head.
+	///
+	/// Meant for building a long history quickly, e.g. in tests. The blocks don't carry any
+	/// messages. Returns `None` if the parent of any block is not known or a block can't be
+	/// produced.
+	pub async fn advance_n(&self, from_head: HeadData, n: u64) -> Option<HeadData> {
+		let mut state = self.state.lock().await;
+		let result = state.advance_n(from_head, n);
+		self.metrics.on_state_entries(state.head_to_state.len());
+		self.metrics.on_best_block(state.fork_tree.best_head().number);
+		result
+			.map_err(|e| log::debug!(target: LOG_TARGET, "can't advance: {:?}", e))
+			.ok()
+	}
+
+	/// Get all the known heads along with the state after them, sorted by block number.
+	pub async fn state_entries(&self) -> Vec<(HeadData, u64)> {
+		self.snapshot().await.entries
+	}
+
+	/// Capture all the known heads and their states, to [`Self::restore`] them later.
+	pub async fn snapshot(&self) -> StateSnapshot {
+		self.state.lock().await.snapshot()
+	}
+
+	/// Replace all the known heads and their states with the ones of `snapshot`.
+	///
+	/// The snapshot must be of the same chain: the genesis of this collator is kept in any case.
+	pub async fn restore(&self, snapshot: StateSnapshot) {
+		let mut state = self.state.lock().await;
+		state.restore(snapshot);
+		self.metrics.on_state_entries(state.head_to_state.len());
+		self.metrics.on_best_block(state.fork_tree.best_head().number);
+	}
+
+	/// Get the head of the longest chain produced by this collator.
+	pub async fn best_head(&self) -> HeadData {
+		self.state.lock().await.fork_tree.best_head().clone()
+	}
+
+	/// Get statistics about the collations produced so far.
+	pub fn stats(&self) -> CollationStats {
+		self.stats.get()
+	}
+
+	/// Get the highest HRMP watermark of the collations produced so far, zero if none were.
+	///
+	/// Never goes backwards, even if a collation is produced on an older relay parent.
+	pub fn hrmp_watermark(&self) -> RelayChainBlockNumber {
+		self.last_hrmp_watermark.load(Ordering::Relaxed)
+	}
+
+	/// Get the number of collations currently awaiting a seconded statement.
+	pub fn pending_seconded(&self) -> usize {
+		self.pending_seconded.load(Ordering::Relaxed)
+	}
+
+	/// Whether collation production was stopped due to a seconded statement not matching our
+	/// collation, see [`SecondedMismatchPolicy::Strict`].
+	pub fn is_halted(&self) -> bool {
+		self.halted.load(Ordering::Relaxed)
+	}
+
+	/// Resume collation production after it was stopped, see [`Self::is_halted`].
+	///
+	/// Does nothing if collation production wasn't stopped.
+	pub fn resume(&self) {
+		if self.halted.swap(false, Ordering::Relaxed) {
+			log::info!(target: LOG_TARGET, "Resuming collation production");
+		}
+	}
+
+	/// Get the heads of the best chain, from the best head back to genesis.
+	///
+	/// If the number of kept states is bounded, see [`Self::set_state_capacity`], the chain ends
+	/// at the oldest ancestor that is still kept.
+	pub async fn canonical_chain(&self) -> Vec<HeadData> {
+		self.state.lock().await.canonical_chain().collect()
+	}
+
+	/// Get the number of the best head, zero for genesis.
+	///
+	/// Cheaper than [`Self::best_head`] as nothing but the number is copied.
+	pub async fn best_number(&self) -> u64 {
+		self.state.lock().await.fork_tree.best_head().number
+	}
+
+	/// Get the hashes of the known children of the head with the given `hash`.
+	///
+	/// More than one child means that the chain forked at that head.
+	pub async fn child_heads(&self, hash: &[u8; 32]) -> Vec<[u8; 32]> {
+		self.state.lock().await.fork_tree.children(hash).to_vec()
+	}
+
+	/// Get the SCALE encoded genesis head as `0x` prefixed hex.
+	pub fn genesis_head_hex(&self) -> String {
+		format!("0x{:?}", HexDisplay::from(&self.genesis_head()))
 	}
 
 	/// Get the validation code of the parachain.
@@ -91,6 +1391,21 @@ impl Collator {
 		pba_pvf::wasm_binary_unwrap()
 	}
 
+	/// Get the validation code as `0x` prefixed hex.
+	pub fn validation_code_hex(&self) -> String {
+		format!("0x{:?}", HexDisplay::from(&self.validation_code()))
+	}
+
+	/// Get the blake2-256 hash of the validation code, which registering the parachain refers to.
+	pub fn validation_code_hash(&self) -> ValidationCodeHash {
+		ValidationCode(self.validation_code().to_vec()).hash()
+	}
+
+	/// Get the hash of the validation code as `0x` prefixed hex.
+	pub fn validation_code_hash_hex(&self) -> String {
+		format!("0x{:?}", HexDisplay::from(&self.validation_code_hash().0.as_ref()))
+	}
+
 	/// Get the collator key.
 	pub fn collator_key(&self) -> CollatorPair {
 		self.key.clone()
@@ -101,6 +1416,210 @@ impl Collator {
 		self.key.public()
 	}
 
+	/// Replace the randomly generated collator key.
+	pub fn set_collator_key(&mut self, key: CollatorPair) {
+		self.key = key;
+	}
+
+	/// Register the collator metrics in the given Prometheus `registry`.
+	///
+	/// Must be called before [`Self::create_collation_function`] to have the metrics reported.
+	pub async fn register_metrics(
+		&mut self,
+		registry: Option<&Registry>,
+	) -> Result<(), PrometheusError> {
+		self.metrics = Metrics::register(registry)?;
+		// The state might have been loaded from disk already.
+		let state = self.state.lock().await;
+		self.metrics.on_state_entries(state.head_to_state.len());
+		self.metrics.on_best_block(state.fork_tree.best_head().number);
+		Ok(())
+	}
+
+	/// Let the operation of every new block be chosen by `operation`, given the block number.
+	///
+	/// By default, every block adds the constant amount of the genesis configuration.
+	pub fn with_operation_fn(self, operation: impl Fn(u64) -> Op + Send + Sync + 'static) -> Self {
+		// A builder method: the collator isn't shared yet, so the lock is free.
+		block_on(self.state.lock()).operation = Some(Arc::new(operation));
+		self
+	}
+
+	/// Keep the state of at most `capacity` heads, evicting the least recently advanced ones.
+	///
+	/// The genesis and the best head are always kept, so the effective minimum is two.
+	pub async fn set_state_capacity(&self, capacity: usize) {
+		let mut state = self.state.lock().await;
+		state.capacity = Some(capacity);
+		state.prune();
+		self.metrics.on_state_entries(state.head_to_state.len());
+	}
+
+	/// Drop collations whose compressed PoV exceeds `size` bytes.
+	///
+	/// Defaults to the relay chain's [`MAX_POV_SIZE`].
+	pub fn set_max_pov_size(&mut self, size: u32) {
+		self.max_pov_size = size;
+	}
+
+	/// Set how to compress the PoV of the collations.
+	///
+	/// Defaults to [`PovCompression::Default`].
+	pub fn set_pov_compression(&mut self, compression: PovCompression) {
+		self.pov_compression = compression;
+	}
+
+	/// Set whether to re-execute every produced block with the validation logic before handing
+	/// out its collation, dropping the collation if the results differ.
+	///
+	/// Off by default, as it doubles the work of producing a block.
+	pub fn set_verify_collations(&mut self, verify: bool) {
+		self.verify_collations = verify;
+	}
+
+	/// Set how much to log about every produced collation.
+	///
+	/// Defaults to [`CollationLogVerbosity::Summary`].
+	pub fn set_collation_log_verbosity(&mut self, verbosity: CollationLogVerbosity) {
+		self.collation_log = verbosity;
+	}
+
+	/// Set what to do when a seconded statement doesn't match our collation.
+	///
+	/// Defaults to [`SecondedMismatchPolicy::Lenient`].
+	pub fn set_seconded_mismatch_policy(&mut self, policy: SecondedMismatchPolicy) {
+		self.seconded_mismatch = policy;
+	}
+
+	/// Set how long to wait for a collation to be seconded before giving up on it.
+	///
+	/// Defaults to [`DEFAULT_SECONDED_TIMEOUT`].
+	pub fn set_seconded_timeout(&mut self, timeout: Duration) {
+		self.seconded_timeout = timeout;
+	}
+
+	/// Set the number of collations awaiting a seconded statement above which a warning is
+	/// logged, as the relay chain may be falling behind.
+	///
+	/// Defaults to [`DEFAULT_PENDING_SECONDED_THRESHOLD`].
+	pub fn set_pending_seconded_threshold(&mut self, threshold: usize) {
+		self.pending_seconded_threshold = threshold;
+	}
+
+	/// Queue a message to be sent to the relay chain with one of the next collations.
+	///
+	/// At most [`MAX_UPWARD_MESSAGE_NUM_PER_CANDIDATE`] queued messages are included per
+	/// collation, in the order they were queued.
+	pub fn enqueue_upward_message(&self, msg: Vec<u8>) -> Result<(), UpwardMessageTooLarge> {
+		if msg.len() > MAX_UPWARD_MESSAGE_SIZE {
+			return Err(UpwardMessageTooLarge(msg.len()))
+		}
+
+		lock(&self.upward_messages).push_back(msg);
+		Ok(())
+	}
+
+	/// Open an HRMP channel to `recipient` with the given limits.
+	///
+	/// This only tells the collator about a channel that is open on the relay chain.
+	pub fn open_hrmp_channel(&self, recipient: ParaId, limits: HrmpChannelLimits) {
+		lock(&self.hrmp_outbox).open_channel(recipient, limits);
+	}
+
+	/// Close the HRMP channel to `recipient`, dropping the messages still queued for it.
+	pub fn close_hrmp_channel(&self, recipient: ParaId) {
+		lock(&self.hrmp_outbox).close_channel(recipient);
+	}
+
+	/// Queue a message to be sent to `recipient` with one of the next collations.
+	///
+	/// At most one message per recipient and [`HRMP_MAX_MESSAGE_NUM_PER_CANDIDATE`] messages in
+	/// total are included per collation.
+	pub fn enqueue_horizontal_message(
+		&self,
+		recipient: ParaId,
+		data: Vec<u8>,
+	) -> Result<(), HorizontalMessageError> {
+		lock(&self.hrmp_outbox).enqueue(recipient, data)
+	}
+
+	/// Feed a downward message from the relay chain to the collator.
+	///
+	/// The relay chain doesn't pass its downward message queue along with the validation data,
+	/// so whoever follows the relay chain state has to provide the messages in order.
+	///
+	/// The next collation processes (i.e. counts and drops) all the messages fed so far and
+	/// reports them in `processed_downward_messages`. If no message was fed, the collation
+	/// processes none, which the relay chain only accepts if its queue for us is empty too.
+	pub fn push_downward_message(&self, msg: InboundDownwardMessage) {
+		lock(&self.downward_messages).push_back(msg);
+	}
+
+	/// Schedule an upgrade of the validation code to the given Wasm `code`.
+	///
+	/// The code is sent with the next collation only, replacing any code scheduled before that
+	/// wasn't sent yet. [`Self::validation_code`] keeps returning the code the collator was built
+	/// with: blocks are still produced natively, so `code` has to accept the same block data.
+	pub fn schedule_validation_code(&self, code: Vec<u8>) {
+		*lock(&self.new_validation_code) = Some(code);
+	}
+
+	/// Get notified of every collation produced from now on.
+	///
+	/// Dropping the receiver unsubscribes.
+	pub fn subscribe_collations(&self) -> mpsc::UnboundedReceiver<ProducedCollation> {
+		let (sender, receiver) = mpsc::unbounded();
+		lock(&self.collation_listeners).push(sender);
+		receiver
+	}
+
+	/// Produce a block on top of the best head every `interval`, without a relay chain.
+	///
+	/// Only meant for exercising the state machine locally during development: the blocks don't
+	/// carry any messages and are never collated. The future never resolves.
+	pub async fn run_standalone(&self, interval: Duration) {
+		loop {
+			futures_timer::Delay::new(interval).await;
+
+			let mut state = self.state.lock().await;
+			let parent = state.fork_tree.best_head().clone();
+			match state.advance(parent, BlockMessages::default()) {
+				Ok(Advanced { head, diff, .. }) => log::info!(
+					target: LOG_TARGET,
+					"produced standalone block ({}): {:?}",
+					diff,
+					head,
+				),
+				Err(e) => log::error!(
+					target: LOG_TARGET,
+					"failed to produce a standalone block: {:?}",
+					e,
+				),
+			}
+			self.metrics.on_state_entries(state.head_to_state.len());
+			self.metrics.on_best_block(state.fork_tree.best_head().number);
+		}
+	}
+
+	/// Log the best block number and the collations produced and pending every `interval`.
+	///
+	/// A heartbeat for long-running nodes, which also logs while no collations are produced. The
+	/// future never resolves.
+	pub async fn log_health(&self, interval: Duration) {
+		loop {
+			futures_timer::Delay::new(interval).await;
+
+			let best_number = self.state.lock().await.fork_tree.best_head().number;
+			log::info!(
+				target: LOG_TARGET,
+				"best block #{}, {} collations produced, {} awaiting a seconded statement",
+				best_number,
+				self.stats().produced,
+				self.pending_seconded(),
+			);
+		}
+	}
+
 	/// Create the collation function.
 	///
 	/// This collation function can be plugged into the overseer to generate collations for the parachain.
@@ -109,60 +1628,297 @@ impl Collator {
 		spawner: impl SpawnNamed + Clone + 'static,
 	) -> CollatorFn {
 		use futures::FutureExt as _;
+		use tracing::Instrument as _;
 
 		let state = self.state.clone();
+		let metrics = self.metrics.clone();
+		let stats = self.stats.clone();
+		let seconded_mismatch = self.seconded_mismatch;
+		let seconded_timeout = self.seconded_timeout;
+		let pending_seconded = self.pending_seconded.clone();
+		let pending_seconded_threshold = self.pending_seconded_threshold;
+		let max_pov_size = self.max_pov_size;
+		let pov_compression = self.pov_compression;
+		match pov_compression {
+			PovCompression::Default => {},
+			PovCompression::None => log::info!(
+				target: LOG_TARGET,
+				"handing out raw PoVs: easier to inspect, but larger to send out",
+			),
+			PovCompression::Zstd(level) => log::info!(
+				target: LOG_TARGET,
+				"compressing PoVs with zstd level {}: higher levels yield smaller PoVs but take \
+				 longer to collate, lower levels the opposite",
+				level,
+			),
+		}
+		let verify_collations = self.verify_collations;
+		let collation_log = self.collation_log;
+		let para_id = self.para_id;
+		let last_hrmp_watermark = self.last_hrmp_watermark.clone();
+		let halted = self.halted.clone();
+		let upward_messages = self.upward_messages.clone();
+		let hrmp_outbox = self.hrmp_outbox.clone();
+		let downward_messages = self.downward_messages.clone();
+		let new_validation_code = self.new_validation_code.clone();
+		let collation_listeners = self.collation_listeners.clone();
 
 		Box::new(move |relay_parent, validation_data| {
-			let parent = HeadData::decode(&mut &validation_data.parent_head.0[..])
-				.expect("Decodes parent head");
-
-			let (block_data, head_data) = state.lock().unwrap().advance(parent);
+			if halted.load(Ordering::Relaxed) {
+				log::debug!(
+					target: LOG_TARGET,
+					"collation production halted, skipping relay-parent({})",
+					relay_parent,
+				);
+				return async { None }.boxed()
+			}
 
-			log::info!(
-				"created a new collation on relay-parent({}): {:?}",
-				relay_parent,
-				block_data,
+			let versioned_parent = match decode_parent_head(&validation_data.parent_head.0) {
+				Ok(parent) => parent,
+				Err(e) => {
+					e.log(relay_parent);
+					return async { None }.boxed()
+				},
+			};
+			let parent = versioned_parent.clone().into_latest();
+			let span = tracing::info_span!(
+				target: LOG_TARGET,
+				"collation",
+				?relay_parent,
+				para_block_number = tracing::field::Empty,
+				pov_size = tracing::field::Empty,
 			);
+			let parent_head = validation_data.parent_head.clone();
+			let relay_parent_number = validation_data.relay_parent_number;
+			let relay_parent_storage_root = validation_data.relay_parent_storage_root;
 
-			let pov = PoV { block_data: block_data.encode().into() };
+			let state = state.clone();
+			let metrics = metrics.clone();
+			let stats = stats.clone();
+			let halted = halted.clone();
+			let upward_messages = upward_messages.clone();
+			let hrmp_outbox = hrmp_outbox.clone();
+			let downward_messages = downward_messages.clone();
+			let new_validation_code = new_validation_code.clone();
+			let collation_listeners = collation_listeners.clone();
+			let last_hrmp_watermark = last_hrmp_watermark.clone();
+			let pending_seconded = pending_seconded.clone();
+			let spawner = spawner.clone();
 
-			let collation = Collation {
-				upward_messages: Vec::new(),
-				horizontal_messages: Vec::new(),
-				new_validation_code: None,
-				head_data: head_data.encode().into(),
-				proof_of_validity: MaybeCompressedPoV::Raw(pov.clone()),
-				processed_downward_messages: 0,
-				hrmp_watermark: validation_data.relay_parent_number,
-			};
+			async move {
+				// Take the state lock first: the message queues are guarded by blocking mutexes,
+				// whose guards must not be held across an await point.
+				let mut state = state.lock().await;
+
+				let mut upward_queue = lock(&upward_messages);
+				let num_upward = upward_queue.len().min(MAX_UPWARD_MESSAGE_NUM_PER_CANDIDATE);
+				let mut hrmp_outbox = lock(&hrmp_outbox);
+				let mut downward_queue = lock(&downward_messages);
+				let mut new_validation_code = lock(&new_validation_code);
+				let messages = BlockMessages {
+					upward: upward_queue.iter().take(num_upward).cloned().collect(),
+					horizontal: hrmp_outbox.select(),
+					processed_downward: downward_queue.len() as u32,
+					new_validation_code: new_validation_code.clone(),
+				};
+
+				// The validation data doesn't carry the message queues themselves, only the
+				// relay chain storage root they could be proven against, so log what we have.
+				if log::log_enabled!(target: LOG_TARGET, log::Level::Debug) {
+					log::debug!(
+						target: LOG_TARGET,
+						"relay-parent({}) #{}: storage root {}, {} downward messages to process, \
+						 {} horizontal messages to send",
+						relay_parent,
+						relay_parent_number,
+						relay_parent_storage_root,
+						downward_queue.len(),
+						messages.horizontal.len(),
+					);
+					for msg in &messages.horizontal {
+						log::debug!(
+							target: LOG_TARGET,
+							"horizontal message to para {}: {} bytes",
+							msg.recipient,
+							msg.data.len(),
+						);
+					}
+				}
 
-			let compressed_pov = polkadot_node_primitives::maybe_compress_pov(pov);
-
-			let (result_sender, recv) = oneshot::channel::<CollationSecondedSignal>();
-			spawner.spawn(
-				"pba-collator-seconded",
-				None,
-				async move {
-					if let Ok(res) = recv.await {
-						if !matches!(
-							res.statement.payload(),
-							Statement::Seconded(s) if s.descriptor.pov_hash == compressed_pov.hash(),
-						) {
+				if state.head_to_state.contains_key(&parent) &&
+					state.fork_tree.best_head() != &parent
+				{
+					log::debug!(
+						target: LOG_TARGET,
+						"relay-parent({}) builds on a fork, our best head is #{}",
+						relay_parent,
+						state.fork_tree.best_head().number,
+					);
+				}
+
+				// Only record the block once it passed all the checks and makes it into a
+				// collation, so that a dropped collation isn't built upon.
+				let advanced = match state.peek_advance(versioned_parent, messages) {
+					Ok(advanced) => advanced,
+					Err(AdvanceError::UnknownParent) =>
+						return Err(CollationError::UnknownParent(parent_head.0)),
+					Err(AdvanceError::Execute(e)) => return Err(CollationError::StateError(e)),
+				};
+				if advanced.duplicate {
+					log::debug!(
+						target: LOG_TARGET,
+						"relay-parent({}) asks for a block we already produced",
+						relay_parent,
+					);
+				}
+
+				if verify_collations {
+					if let Err(e) = verify_block(
+						&parent_head.0,
+						&advanced.block_data,
+						&advanced.head,
+						advanced.diff.after,
+					) {
+						return Err(CollationError::Verify(advanced.head.number, e))
+					}
+				}
+
+				let (collation, pov_hash, pov_size) = build_collation(
+					&advanced.block_data,
+					&advanced.head,
+					relay_parent_number,
+					pov_compression,
+				);
+				metrics.on_pov_encoded(pov_size);
+				tracing::Span::current()
+					.record("para_block_number", advanced.head.number)
+					.record("pov_size", pov_size.raw);
+
+				if pov_size.sent() > max_pov_size as usize {
+					return Err(CollationError::OversizedPov { size: pov_size.sent(), max_pov_size })
+				}
+
+				state.record(&parent, &advanced);
+				metrics.on_state_entries(state.head_to_state.len());
+				metrics.on_best_block(state.fork_tree.best_head().number);
+				let Advanced { block_data, head: head_data, diff, .. } = advanced;
+
+				match collation_log {
+					CollationLogVerbosity::Off => {},
+					CollationLogVerbosity::Summary => log::info!(
+						target: LOG_TARGET,
+						"created collation #{} of para {} on relay-parent({}), state {}, PoV {}",
+						head_data.number,
+						para_id,
+						relay_parent,
+						diff,
+						pov_size,
+					),
+					CollationLogVerbosity::Full => log::info!(
+						target: LOG_TARGET,
+						"created collation #{} of para {} on relay-parent({}), state {}, PoV {}: \
+						 {:?}",
+						head_data.number,
+						para_id,
+						relay_parent,
+						diff,
+						pov_size,
+						block_data,
+					),
+				}
+
+				// The relay chain rejects candidates whose watermark is below the one of their
+				// parachain parent, which is likely the case here unless we are on a fork.
+				let last_watermark =
+					last_hrmp_watermark.fetch_max(relay_parent_number, Ordering::Relaxed);
+				if relay_parent_number == 0 {
+					log::info!(
+						target: LOG_TARGET,
+						"collating on the relay chain genesis, the HRMP watermark is 0",
+					);
+				} else if relay_parent_number < last_watermark {
+					log::warn!(
+						target: LOG_TARGET,
+						"HRMP watermark of relay-parent({}) goes back from #{} to #{}",
+						relay_parent,
+						last_watermark,
+						relay_parent_number,
+					);
+				}
+
+				// Only dequeue the messages once they made it into a collation.
+				upward_queue.drain(..num_upward);
+				hrmp_outbox.remove_sent(&block_data.horizontal_messages);
+				*new_validation_code = None;
+				for msg in downward_queue.drain(..) {
+					log::debug!(
+						target: LOG_TARGET,
+						"processed downward message sent at #{}: {} bytes",
+						msg.sent_at,
+						msg.msg.len(),
+					);
+				}
+
+				drop(upward_queue);
+				drop(hrmp_outbox);
+				drop(downward_queue);
+				drop(new_validation_code);
+				drop(state);
+
+				let (result_sender, recv) = oneshot::channel::<CollationSecondedSignal>();
+				let pending = PendingSeconded::new(pending_seconded);
+				if pending.count() > pending_seconded_threshold {
+					log::warn!(
+						target: LOG_TARGET,
+						"{} collations are awaiting a seconded statement, the relay chain may be \
+						 falling behind",
+						pending.count(),
+					);
+				}
+				spawner.spawn(
+					"pba-collator-seconded",
+					None,
+					async move {
+						let _pending = pending;
+						if let Err(SecondedMismatch(statement)) =
+							wait_for_seconded(recv, pov_hash, seconded_timeout).await
+						{
 							log::error!(
+								target: LOG_TARGET,
 								"Seconded statement should match our collation: {:?}",
-								res.statement.payload()
+								statement
 							);
-							std::process::exit(-1);
-						}
 
-						log::info!("Our collation was seconded! {:?}", res,);
+							if seconded_mismatch == SecondedMismatchPolicy::Strict {
+								log::error!(
+									target: LOG_TARGET,
+									"Halting collation production until resumed",
+								);
+								halted.store(true, Ordering::Relaxed);
+							}
+						}
 					}
-				}
-				.boxed(),
-			);
+					.instrument(tracing::Span::current())
+					.boxed(),
+				);
+
+				metrics.on_collation_produced();
+				stats.on_collation_produced(pov_size.raw);
+
+				let produced = ProducedCollation {
+					head_hash: head_data.hash(),
+					number: head_data.number,
+					pov_size: pov_size.raw,
+					diff,
+				};
+				lock(&collation_listeners)
+					.retain(|listener| listener.unbounded_send(produced.clone()).is_ok());
 
-			async move { Some(CollationResult { collation, result_sender: Some(result_sender) }) }
-				.boxed()
+				Ok(CollationResult { collation, result_sender: Some(result_sender) })
+			}
+			.map(move |result| result.map_err(|e| e.log(relay_parent)).ok())
+			.instrument(span)
+			.boxed()
 		})
 	}
 }
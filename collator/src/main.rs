@@ -16,45 +16,377 @@
 
 //! Collator for the PBA parachain.
 
-use pba_parachain_collator::Collator;
+use parity_scale_codec::{Decode, Encode};
+use pba_parachain_collator::{
+	collator_key_from_file, collator_key_from_seed,
+	rpc::{Para, ParaApiServer},
+	Collator, CollatorConfig, PovCompression, DEFAULT_ADD, DEFAULT_GENESIS_VALUE, LOG_TARGET,
+};
+use pba_pvf::{ArithmeticMode, BlockData, HeadData, Op, VersionedHeadData};
 use polkadot_cli::{Error, Result};
-use polkadot_node_primitives::CollationGenerationConfig;
+use polkadot_node_primitives::{CollationGenerationConfig, PoV, POV_BOMB_LIMIT};
 use polkadot_node_subsystem::messages::{CollationGenerationMessage, CollatorProtocolMessage};
-use polkadot_primitives::Id as ParaId;
+use polkadot_primitives::{CollatorPair, Id as ParaId};
 use sc_cli::{Error as SubstrateCliError, SubstrateCli};
-use sp_core::hexdisplay::HexDisplay;
+use sc_service::RpcMethods;
+use sp_core::{hexdisplay::HexDisplay, Pair};
+use std::{
+	io::Write,
+	path::Path,
+	sync::Arc,
+	time::{Duration, Instant},
+};
 
-/// The parachain ID to collate for in case it wasn't set explicitly through CLI.
-const DEFAULT_PARA_ID: ParaId = ParaId::new(100);
+/// Name of the directory, next to the relay-chain database, holding the parachain state.
+#[cfg(feature = "persistence")]
+const STATE_DB_DIR: &str = "pba-state";
+
+/// Path of the persisted parachain state for the given node configuration.
+#[cfg(feature = "persistence")]
+fn state_db_path(config: &sc_service::Configuration) -> Option<std::path::PathBuf> {
+	config.database.path().map(|db| db.with_file_name(STATE_DB_DIR))
+}
+
+/// Write `data` to `output`, or to stdout if no path is given.
+///
+/// The data is written as its `0x` prefixed `hex` representation unless `raw` is set.
+fn write_output(data: &[u8], hex: &str, raw: bool, output: Option<&Path>) -> Result<()> {
+	let result = if raw {
+		match output {
+			Some(path) => std::fs::write(path, data),
+			None => std::io::stdout().write_all(data),
+		}
+	} else {
+		match output {
+			Some(path) => std::fs::write(path, hex),
+			None => writeln!(std::io::stdout(), "{}", hex),
+		}
+	};
+	result.map_err(SubstrateCliError::from)?;
+
+	Ok(())
+}
+
+/// Write the `secret` to a new file at `path`, readable by its owner only.
+///
+/// Fails if the file already exists rather than overwriting a secret.
+fn write_secret(path: &Path, secret: &str) -> std::io::Result<()> {
+	let mut options = std::fs::OpenOptions::new();
+	options.write(true).create_new(true);
+	#[cfg(unix)]
+	std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+	options.open(path)?.write_all(secret.as_bytes())
+}
+
+/// Decode a SCALE encoded command line argument.
+fn decode_arg<T: Decode>(name: &str, mut data: &[u8]) -> Result<T> {
+	T::decode(&mut data)
+		.map_err(|e| SubstrateCliError::Input(format!("Invalid {}: {}", name, e)).into())
+}
+
+/// Execute a chain of `iterations` blocks applying `ops` each, returning the time spent in
+/// [`pba_pvf::execute`].
+fn bench_execute(iterations: u32, ops: &[Op]) -> Duration {
+	let mut head = pba_pvf::genesis_head(DEFAULT_GENESIS_VALUE, ArithmeticMode::default());
+	let mut state = DEFAULT_GENESIS_VALUE;
+	let mut elapsed = Duration::ZERO;
+
+	for _ in 0..iterations {
+		let block = BlockData {
+			state,
+			ops: ops.to_vec(),
+			timestamp: head.timestamp + 1,
+			..Default::default()
+		};
+		state = block.apply().expect("Wrapping arithmetic doesn't fail");
+
+		let start = Instant::now();
+		head = pba_pvf::execute(head.hash(), head, &block).expect("Block builds on its parent");
+		elapsed += start.elapsed();
+	}
+
+	elapsed
+}
+
+/// Benchmark [`pba_pvf::execute`] and print the results as a table.
+///
+/// A single operation per block is dominated by hashing, the maximum number of operations by the
+/// arithmetic.
+fn benchmark(iterations: u32) {
+	let arithmetic = [Op::Add(DEFAULT_ADD), Op::Mul(3), Op::Sub(1)]
+		.iter()
+		.cycle()
+		.copied()
+		.take(pba_pvf::MAX_OPS_PER_BLOCK)
+		.collect::<Vec<_>>();
+
+	println!("{:>10} {:>10} {:>12} {:>10}", "ops/block", "blocks", "blocks/sec", "ns/call");
+	for ops in [&[Op::Add(DEFAULT_ADD)][..], &arithmetic[..]] {
+		let elapsed = bench_execute(iterations, ops);
+		println!(
+			"{:>10} {:>10} {:>12.0} {:>10}",
+			ops.len(),
+			iterations,
+			iterations as f64 / elapsed.as_secs_f64(),
+			elapsed.as_nanos() / u128::from(iterations.max(1)),
+		);
+	}
+}
+
+/// Execute the blocks encoded back to back in `blocks` on top of `head`, printing every new head.
+///
+/// Stops at the first block that fails to decode or execute.
+fn replay(mut head: HeadData, mut blocks: &[u8]) -> Result<()> {
+	let mut index = 0;
+	while !blocks.is_empty() {
+		let block_data = BlockData::decode(&mut blocks).map_err(|e| {
+			SubstrateCliError::Input(format!("Block #{} failed to decode: {}", index, e))
+		})?;
+		head = pba_pvf::execute(head.hash(), head, &block_data).map_err(|e| {
+			SubstrateCliError::Input(format!("Block #{} failed to execute: {:?}", index, e))
+		})?;
+		println!("#{}: {:?} (0x{:?})", index, head, HexDisplay::from(&head.encode()));
+
+		index += 1;
+	}
+
+	Ok(())
+}
+
+/// Print the state of every head known to `collator`, as a table or as JSON.
+fn dump_state(collator: &Collator, json: bool) -> Result<()> {
+	// Nothing else holds the lock of a collator that doesn't run.
+	let entries = futures::executor::block_on(collator.state_entries());
+
+	if json {
+		let entries = entries
+			.iter()
+			.map(|(head, state)| {
+				serde_json::json!({
+					"hash": format!("0x{:?}", HexDisplay::from(&head.hash())),
+					"number": head.number,
+					"state": state,
+				})
+			})
+			.collect::<Vec<_>>();
+		let json = serde_json::to_string_pretty(&entries)
+			.map_err(|e| SubstrateCliError::Application(Box::new(e)))?;
+		println!("{}", json);
+	} else {
+		println!("{:<66} {:>10} {:>20}", "hash", "number", "state");
+		for (head, state) in entries {
+			println!("0x{:?} {:>10} {:>20}", HexDisplay::from(&head.hash()), head.number, state);
+		}
+	}
+
+	Ok(())
+}
 
 mod cli;
 use cli::Cli;
 
 fn main() -> Result<()> {
-	let cli = Cli::from_args();
+	let mut cli = Cli::from_args();
+	if let Some(relay_chain) = cli.run.relay_chain.take() {
+		cli.run.base.shared_params.chain = Some(relay_chain);
+	}
+	if let Some(level) = cli.run.collator_log_level {
+		// Appended last, so that it overrides any `--log` directive for the same target.
+		cli.run.base.shared_params.log.push(format!("{}={}", LOG_TARGET, level));
+	}
 
 	match cli.subcommand {
-		Some(cli::Subcommand::ExportGenesisState(_params)) => {
-			let collator = Collator::new();
-			println!("0x{:?}", HexDisplay::from(&collator.genesis_head()));
+		Some(cli::Subcommand::ExportGenesisState(params)) => {
+			let collator =
+				Collator::from_config(CollatorConfig::default().genesis(params.genesis.load()?));
+			write_output(
+				collator.genesis_head(),
+				&collator.genesis_head_hex(),
+				params.raw,
+				params.output.as_deref(),
+			)
+		},
+		Some(cli::Subcommand::ExportGenesisConfig(params)) => {
+			let collator =
+				Collator::from_config(CollatorConfig::default().genesis(params.genesis.load()?));
+			let json = serde_json::to_string_pretty(&serde_json::json!({
+				"para_id": params.parachain_id,
+				"genesis_head": collator.genesis_head_hex(),
+				"validation_code_hash": collator.validation_code_hash_hex(),
+			}))
+			.map_err(|e| SubstrateCliError::Application(Box::new(e)))?;
+			write_output(json.as_bytes(), &json, false, params.output.as_deref())
+		},
+		Some(cli::Subcommand::ExportGenesisWasm(params)) => {
+			let collator = Collator::default();
+			if params.hash_only {
+				println!("{}", collator.validation_code_hash_hex());
+			} else {
+				println!("{}", collator.validation_code_hex());
+			}
 
 			Ok::<_, Error>(())
 		},
-		Some(cli::Subcommand::ExportGenesisWasm(_params)) => {
-			let collator = Collator::new();
-			println!("0x{:?}", HexDisplay::from(&collator.validation_code()));
+		Some(cli::Subcommand::ValidateBlock(params)) => {
+			let parent_head: VersionedHeadData = decode_arg("parent head", &params.parent_head)?;
+			let block_data: BlockData = decode_arg("block data", &params.block_data)?;
+
+			match pba_pvf::execute(parent_head.hash(), parent_head, &block_data) {
+				Ok(head) => println!(
+					"Valid block, new head: {:?} (0x{:?})",
+					head,
+					HexDisplay::from(&head.encode()),
+				),
+				Err(e) => println!("Invalid block: {:?}", e),
+			}
+
+			Ok(())
+		},
+		Some(cli::Subcommand::VerifyPov(params)) => {
+			let parent_head: VersionedHeadData = decode_arg("parent head", &params.parent_head)?;
+			let pov_bytes = std::fs::read(&params.pov).map_err(SubstrateCliError::from)?;
+			let pov: PoV = decode_arg("PoV", &pov_bytes)?;
+			println!("PoV hash: {:?}", pov.hash());
+
+			let block_data =
+				sp_maybe_compressed_blob::decompress(&pov.block_data.0, POV_BOMB_LIMIT)
+					.map_err(|e| SubstrateCliError::Input(format!("Invalid PoV: {}", e)))?;
+			let block_data: BlockData = decode_arg("block data", &block_data)?;
+
+			match pba_pvf::execute(parent_head.hash(), parent_head, &block_data) {
+				Ok(head) => println!(
+					"Valid PoV, new head: {:?} (0x{:?})",
+					head,
+					HexDisplay::from(&head.encode()),
+				),
+				Err(e) => println!("Invalid PoV: {:?}", e),
+			}
+
+			Ok(())
+		},
+		Some(cli::Subcommand::GenerateCollatorKey(params)) => {
+			let (key, phrase, _) = CollatorPair::generate_with_phrase(None);
+			if let Some(path) = &params.output {
+				write_secret(path, &phrase).map_err(|e| {
+					SubstrateCliError::Input(format!("Can't write {}: {}", path.display(), e))
+				})?;
+				println!("Secret phrase written to {}", path.display());
+			} else {
+				println!("Secret phrase: {}", phrase);
+			}
+			println!("Collator id:   {}", key.public());
+
+			Ok(())
+		},
+		Some(cli::Subcommand::Benchmark(params)) => {
+			benchmark(params.iterations);
 
 			Ok(())
 		},
+		Some(cli::Subcommand::Replay(params)) => {
+			let head = match &params.from_head {
+				Some(head) => decode_arg("head", head)?,
+				None => params.genesis.load()?.genesis_head(),
+			};
+			let blocks = std::fs::read(&params.blocks).map_err(SubstrateCliError::from)?;
+
+			replay(head, &blocks)
+		},
+		Some(cli::Subcommand::DumpState(cmd)) => {
+			let config = CollatorConfig::default().genesis(cmd.genesis.load()?);
+			let runner = cli.create_runner(&cmd)?;
+			Ok(runner.sync_run(|_config| {
+				#[cfg(feature = "persistence")]
+				let collator = match state_db_path(&_config) {
+					Some(path) => Collator::open(config, path)
+						.map_err(|e| SubstrateCliError::Application(Box::new(e)))?,
+					None => Collator::from_config(config),
+				};
+				#[cfg(not(feature = "persistence"))]
+				let collator = Collator::from_config(config);
+
+				dump_state(&collator, cmd.json)
+			})?)
+		},
+		Some(cli::Subcommand::PurgeChain(cmd)) => {
+			let runner = cli.create_runner(&cmd)?;
+			Ok(runner.sync_run(|config| {
+				#[cfg(feature = "persistence")]
+				let state_db = state_db_path(&config);
+				#[cfg(not(feature = "persistence"))]
+				let state_db = None;
+
+				cmd.run(config.database, state_db)
+			})?)
+		},
 		None => {
+			let para_id = cli.run.parachain_id.map(ParaId::from).ok_or_else(|| {
+				SubstrateCliError::Input("--parachain-id is required to run the collator".into())
+			})?;
+			let mut genesis = cli.run.genesis.load()?;
+			genesis.add = Some(genesis.resolve_add(cli.run.add));
 			let runner = cli.create_runner(&cli.run.base).map_err(|e| {
 				SubstrateCliError::Application(
 					Box::new(e) as Box<(dyn 'static + Send + Sync + std::error::Error)>
 				)
 			})?;
 
-			runner.run_node_until_exit(|config| async move {
-				let collator = Collator::new();
+			runner.run_node_until_exit(|mut config| async move {
+				let mut collator_config = CollatorConfig::default()
+					.para_id(para_id)
+					.genesis(genesis)
+					.pov_compression(
+						match (cli.run.no_pov_compression, cli.run.pov_compression_level) {
+							(true, _) => PovCompression::None,
+							(false, Some(level)) => PovCompression::Zstd(level),
+							(false, None) => PovCompression::Default,
+						},
+					)
+					.verify_collations(cli.run.verify_collations);
+				let collator_key = match (&cli.run.collator_seed, &cli.run.collator_key_file) {
+					(Some(seed), _) => Some(collator_key_from_seed(seed)),
+					(None, Some(path)) => Some(collator_key_from_file(path)),
+					(None, None) => None,
+				};
+				if let Some(key) = collator_key {
+					collator_config = collator_config.collator_key(key.map_err(|e| e.to_string())?);
+				}
+
+				#[cfg(feature = "persistence")]
+				let mut collator = match state_db_path(&config) {
+					Some(path) =>
+						Collator::open(collator_config, path).map_err(|e| e.to_string())?,
+					None => Collator::from_config(collator_config),
+				};
+				#[cfg(not(feature = "persistence"))]
+				let mut collator = Collator::from_config(collator_config);
+				collator.verify_genesis().await.map_err(|e| {
+					format!("Genesis doesn't pass the validation code's checks: {:?}", e)
+				})?;
+				collator
+					.register_metrics(config.prometheus_registry())
+					.await
+					.map_err(|e| e.to_string())?;
+				let collator = Arc::new(collator);
+
+				// The node's RPC server can't be extended with further methods, so we take over its
+				// address and serve the node's methods along with the parachain ones. The node's
+				// module allows its unsafe methods, so this is only done where the node would too.
+				let rpc_addr = config.rpc_http.filter(|addr| match config.rpc_methods {
+					RpcMethods::Unsafe => true,
+					RpcMethods::Safe => false,
+					RpcMethods::Auto => addr.ip().is_loopback(),
+				});
+				if rpc_addr.is_some() {
+					config.rpc_http = None;
+				} else if let Some(addr) = config.rpc_http {
+					log::warn!(
+						target: LOG_TARGET,
+						"parachain RPC methods are not served on {} unless unsafe RPC methods are \
+						 allowed there",
+						addr,
+					);
+				}
 
 				let full_node = polkadot_service::build_full(
 					config,
@@ -74,16 +406,49 @@ fn main() -> Result<()> {
 					.overseer_handle
 					.expect("Overseer handle should be initialized for collators");
 
-				let genesis_head_hex =
-					format!("0x{:?}", HexDisplay::from(&collator.genesis_head()));
-				let validation_code_hex =
-					format!("0x{:?}", HexDisplay::from(&collator.validation_code()));
+				if let Some(addr) = rpc_addr {
+					let mut module = (*full_node.rpc_handlers.handle()).clone();
+					module
+						.merge(
+							Para::new(
+								collator.clone(),
+								Arc::new(full_node.task_manager.spawn_handle()),
+							)
+							.into_rpc(),
+						)
+						.map_err(|e| e.to_string())?;
+					let server = jsonrpsee::server::ServerBuilder::default()
+						.build(addr)
+						.await
+						.map_err(|e| e.to_string())?;
+					let handle = server.start(module).map_err(|e| e.to_string())?;
+					full_node.task_manager.spawn_handle().spawn("pba-rpc", None, handle.stopped());
+					log::info!(target: LOG_TARGET, "Parachain RPC methods served on {}", addr);
+				}
 
-				let para_id = cli.run.parachain_id.map(ParaId::from).unwrap_or(DEFAULT_PARA_ID);
+				if cli.run.health_log_interval > 0 {
+					let collator = collator.clone();
+					let interval = Duration::from_secs(cli.run.health_log_interval);
+					full_node.task_manager.spawn_handle().spawn(
+						"pba-collator-health",
+						None,
+						async move { collator.log_health(interval).await },
+					);
+				}
 
-				log::info!("Running pba collator for parachain id: {}", para_id);
-				log::info!("Genesis state: {}", genesis_head_hex);
-				log::info!("Validation code: {}", validation_code_hex);
+				let para_id = collator.para_id();
+				log::info!(
+					target: LOG_TARGET,
+					"Running pba collator for parachain id: {}",
+					para_id,
+				);
+				log::info!(target: LOG_TARGET, "Collator id: {}", collator.collator_id());
+				log::info!(target: LOG_TARGET, "Genesis state: {}", collator.genesis_head_hex());
+				log::info!(
+					target: LOG_TARGET,
+					"Validation code: {}",
+					collator.validation_code_hex(),
+				);
 
 				let config = CollationGenerationConfig {
 					key: collator.collator_key(),
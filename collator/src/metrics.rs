@@ -0,0 +1,121 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Prometheus metrics of the collator.
+
+use crate::PovSize;
+use prometheus_endpoint::{
+	exponential_buckets, register, Counter, Gauge, Histogram, HistogramOpts, PrometheusError,
+	Registry, U64,
+};
+
+#[derive(Clone)]
+struct MetricsInner {
+	collations_produced: Counter<U64>,
+	pov_size: Histogram,
+	compressed_pov_size: Histogram,
+	state_entries: Gauge<U64>,
+	best_block_number: Gauge<U64>,
+}
+
+/// Collator metrics.
+///
+/// All methods are no-ops if the metrics were not registered.
+#[derive(Default, Clone)]
+pub struct Metrics(Option<MetricsInner>);
+
+impl Metrics {
+	/// Register the metrics in the given `registry`.
+	///
+	/// Returns disabled metrics if `registry` is `None`.
+	pub fn register(registry: Option<&Registry>) -> Result<Self, PrometheusError> {
+		let registry = match registry {
+			Some(registry) => registry,
+			None => return Ok(Self(None)),
+		};
+
+		let collations_produced = register(
+			Counter::new(
+				"collations_produced_total",
+				"Number of collations produced by the collator.",
+			)?,
+			registry,
+		)?;
+		let pov_size = register(
+			Histogram::with_opts(
+				HistogramOpts::new("pov_size_bytes", "Size of the produced PoVs in bytes.")
+					.buckets(exponential_buckets(64.0, 4.0, 10)?),
+			)?,
+			registry,
+		)?;
+		let compressed_pov_size = register(
+			Histogram::with_opts(
+				HistogramOpts::new(
+					"compressed_pov_size_bytes",
+					"Size of the produced PoVs as sent to the validators, in bytes.",
+				)
+				.buckets(exponential_buckets(64.0, 4.0, 10)?),
+			)?,
+			registry,
+		)?;
+
+		let state_entries = register(
+			Gauge::new("para_state_entries", "Number of heads whose state the collator keeps.")?,
+			registry,
+		)?;
+		let best_block_number = register(
+			Gauge::new("para_best_block_number", "Number of the best parachain block.")?,
+			registry,
+		)?;
+
+		Ok(Self(Some(MetricsInner {
+			collations_produced,
+			pov_size,
+			compressed_pov_size,
+			state_entries,
+			best_block_number,
+		})))
+	}
+
+	/// Record the size of an encoded PoV, before and after compression.
+	pub fn on_pov_encoded(&self, size: PovSize) {
+		if let Some(metrics) = &self.0 {
+			metrics.pov_size.observe(size.raw as f64);
+			metrics.compressed_pov_size.observe(size.sent() as f64);
+		}
+	}
+
+	/// Record the number of heads whose state is kept.
+	pub fn on_state_entries(&self, entries: usize) {
+		if let Some(metrics) = &self.0 {
+			metrics.state_entries.set(entries as u64);
+		}
+	}
+
+	/// Record the number of the best head.
+	pub fn on_best_block(&self, number: u64) {
+		if let Some(metrics) = &self.0 {
+			metrics.best_block_number.set(number);
+		}
+	}
+
+	/// Record a produced collation.
+	pub fn on_collation_produced(&self) {
+		if let Some(metrics) = &self.0 {
+			metrics.collations_produced.inc();
+		}
+	}
+}
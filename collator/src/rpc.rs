@@ -0,0 +1,128 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! JSON-RPC interface to the parachain state.
+
+use crate::{Collator, ProducedCollation};
+use futures::{FutureExt, StreamExt};
+use jsonrpsee::{
+	core::{async_trait, Error as JsonRpseeError, RpcResult},
+	proc_macros::rpc,
+	types::{
+		error::{CallError, ErrorObject},
+		SubscriptionResult,
+	},
+	SubscriptionSink,
+};
+use parity_scale_codec::Encode;
+use polkadot_primitives::Hash;
+use serde::Serialize;
+use sp_core::{traits::SpawnNamed, Bytes};
+use std::sync::Arc;
+
+/// Error code returned for heads not known to the collator.
+pub const UNKNOWN_HEAD: i32 = 1;
+
+/// Notification sent for every produced collation.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollationEvent {
+	/// Hash of the new head.
+	pub head_hash: Hash,
+	/// Number of the new block.
+	pub number: u64,
+	/// Size of the uncompressed PoV, in bytes.
+	pub pov_size: u64,
+}
+
+impl From<ProducedCollation> for CollationEvent {
+	fn from(collation: ProducedCollation) -> Self {
+		Self {
+			head_hash: collation.head_hash.into(),
+			number: collation.number,
+			pov_size: collation.pov_size as u64,
+		}
+	}
+}
+
+/// Parachain RPC methods.
+#[rpc(server, namespace = "para")]
+pub trait ParaApi {
+	/// Get the numeric state after the head with the given hash.
+	#[method(name = "stateAt")]
+	async fn state_at(&self, head_hash: Hash) -> RpcResult<u64>;
+
+	/// Get the SCALE encoded head of the longest chain produced by the collator.
+	#[method(name = "bestHead")]
+	async fn best_head(&self) -> RpcResult<Bytes>;
+
+	/// Get notified of every collation produced by the collator.
+	#[subscription(
+		name = "subscribeCollations" => "collation",
+		unsubscribe = "unsubscribeCollations",
+		item = CollationEvent,
+	)]
+	fn subscribe_collations(&self);
+}
+
+/// Implementation of [`ParaApiServer`] on top of a [`Collator`].
+pub struct Para {
+	collator: Arc<Collator>,
+	/// Runs the subscription tasks.
+	executor: Arc<dyn SpawnNamed>,
+}
+
+impl Para {
+	/// Create the RPC handler for the given `collator`.
+	pub fn new(collator: Arc<Collator>, executor: Arc<dyn SpawnNamed>) -> Self {
+		Self { collator, executor }
+	}
+}
+
+#[async_trait]
+impl ParaApiServer for Para {
+	async fn state_at(&self, head_hash: Hash) -> RpcResult<u64> {
+		self.collator.state_at(head_hash.as_fixed_bytes()).await.ok_or_else(|| {
+			JsonRpseeError::Call(CallError::Custom(ErrorObject::owned(
+				UNKNOWN_HEAD,
+				format!("Unknown head {:?}", head_hash),
+				None::<()>,
+			)))
+		})
+	}
+
+	async fn best_head(&self) -> RpcResult<Bytes> {
+		Ok(self.collator.best_head().await.encode().into())
+	}
+
+	fn subscribe_collations(&self, mut sink: SubscriptionSink) -> SubscriptionResult {
+		sink.accept()?;
+
+		// Ends once the client unsubscribes, which drops the receiver and thereby unsubscribes
+		// from the collator as well.
+		let stream = self.collator.subscribe_collations().map(CollationEvent::from);
+		self.executor.spawn(
+			"pba-para-rpc-subscription",
+			Some("rpc"),
+			async move {
+				sink.pipe_from_stream(stream).await;
+			}
+			.boxed(),
+		);
+
+		Ok(())
+	}
+}
@@ -0,0 +1,61 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Resolution and checks of the amount added by every block.
+
+use futures::executor::block_on;
+use pba_parachain_collator::{resolve_add, Collator, CollatorConfig, GenesisConfig, DEFAULT_ADD};
+use pba_pvf::{ExecuteError, MAX_ADD_PER_BLOCK};
+
+#[test]
+fn cli_takes_precedence() {
+	assert_eq!(resolve_add(Some(1), Some(2), Some("3")), 1);
+}
+
+#[test]
+fn config_takes_precedence_over_env() {
+	assert_eq!(resolve_add(None, Some(2), Some("3")), 2);
+}
+
+#[test]
+fn env_takes_precedence_over_default() {
+	assert_eq!(resolve_add(None, None, Some("3")), 3);
+}
+
+#[test]
+fn falls_back_to_default() {
+	assert_eq!(resolve_add(None, None, None), DEFAULT_ADD);
+	assert_eq!(GenesisConfig::default().add, None);
+}
+
+#[test]
+fn invalid_env_is_ignored() {
+	assert_eq!(resolve_add(None, None, Some("seven")), DEFAULT_ADD);
+}
+
+#[test]
+fn add_above_the_block_limit_fails_the_genesis_check() {
+	let collator = |add| {
+		let genesis = GenesisConfig { add: Some(add), ..Default::default() };
+		Collator::from_config(CollatorConfig::default().genesis(genesis))
+	};
+
+	assert_eq!(block_on(collator(MAX_ADD_PER_BLOCK).verify_genesis()), Ok(()));
+	assert_eq!(
+		block_on(collator(MAX_ADD_PER_BLOCK + 1).verify_genesis()),
+		Err(ExecuteError::AddTooLarge),
+	);
+}
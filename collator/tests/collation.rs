@@ -0,0 +1,174 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Runs the collation function the way the collation generation subsystem does.
+
+mod common;
+
+use common::{validation_data, MockSpawner};
+use futures::executor::block_on;
+use parity_scale_codec::{Decode, Encode};
+use pba_parachain_collator::{
+	build_collation, decode_parent_head, ArithmeticMode, CollationError, Collator, CollatorConfig,
+	GenesisConfig, PovCompression,
+};
+use pba_pvf::{BlockData, HeadData, Op};
+use polkadot_node_primitives::{MaybeCompressedPoV, PoV, POV_BOMB_LIMIT};
+use polkadot_primitives::Hash;
+
+#[test]
+fn collates_on_top_of_genesis() {
+	let collator = Collator::default();
+	let spawner = MockSpawner::default();
+	let collation_function = collator.create_collation_function(spawner.clone());
+
+	let data = validation_data(collator.genesis_head().to_vec());
+	let result = block_on(collation_function(Default::default(), &data))
+		.expect("a collation is produced on top of genesis");
+
+	let head = HeadData::decode(&mut &result.collation.head_data.0[..]).unwrap();
+	assert_eq!(head.number, 1);
+	assert_eq!(spawner.task_names(), vec!["pba-collator-seconded"]);
+}
+
+#[test]
+fn collates_on_the_relay_chain_genesis() {
+	let collator = Collator::default();
+	let collation_function = collator.create_collation_function(MockSpawner::default());
+
+	let mut data = validation_data(collator.genesis_head().to_vec());
+	data.relay_parent_number = 0;
+	let result = block_on(collation_function(Default::default(), &data))
+		.expect("a collation is produced on the relay chain genesis");
+
+	assert_eq!(result.collation.hrmp_watermark, 0);
+}
+
+#[test]
+fn hrmp_watermark_does_not_go_backwards() {
+	let collator = Collator::default();
+	let collation_function = collator.create_collation_function(MockSpawner::default());
+	let mut data = validation_data(collator.genesis_head().to_vec());
+
+	for relay_parent_number in [5, 3] {
+		data.relay_parent_number = relay_parent_number;
+		let result = block_on(collation_function(Default::default(), &data))
+			.expect("a collation is produced on top of genesis");
+
+		// The watermark of a collation may not exceed its relay parent number.
+		assert_eq!(result.collation.hrmp_watermark, relay_parent_number);
+		assert_eq!(collator.hrmp_watermark(), 5);
+	}
+}
+
+#[test]
+fn collating_again_on_the_same_parent_reproduces_the_block() {
+	let collator = Collator::default();
+	let collation_function = collator.create_collation_function(MockSpawner::default());
+	let data = validation_data(collator.genesis_head().to_vec());
+
+	let first = block_on(collation_function(Default::default(), &data)).unwrap();
+	// Let the wall clock move on, which the second block must not pick up.
+	std::thread::sleep(std::time::Duration::from_millis(5));
+	let second = block_on(collation_function(Default::default(), &data)).unwrap();
+
+	assert_eq!(first.collation.head_data, second.collation.head_data);
+	let genesis = HeadData::decode(&mut collator.genesis_head()).unwrap();
+	block_on(async {
+		assert_eq!(collator.child_heads(&genesis.hash()).await.len(), 1);
+		assert_eq!(collator.state_entries().await.len(), 2);
+	});
+}
+
+/// Run the collation function of `collator` on `parent_head`, checking that it gives up without
+/// spawning anything.
+fn assert_no_collation(collator: &Collator, parent_head: Vec<u8>) {
+	let spawner = MockSpawner::default();
+	let collation_function = collator.create_collation_function(spawner.clone());
+
+	let result = block_on(collation_function(Default::default(), &validation_data(parent_head)));
+	assert!(result.is_none());
+	assert!(spawner.task_names().is_empty());
+}
+
+#[test]
+fn undecodable_parent_yields_no_collation() {
+	assert_no_collation(&Collator::default(), vec![0xff; 3]);
+}
+
+#[test]
+fn empty_parent_yields_no_collation() {
+	assert!(matches!(decode_parent_head(&[]), Err(CollationError::DecodeParent { len: 0, .. })));
+	assert_no_collation(&Collator::default(), Vec::new());
+}
+
+#[test]
+fn unknown_parent_yields_no_collation() {
+	let unknown = pba_pvf::genesis_head(42, ArithmeticMode::Wrapping);
+	assert_no_collation(&Collator::default(), unknown.encode());
+}
+
+#[test]
+fn oversized_pov_yields_no_collation() {
+	let collator = Collator::from_config(CollatorConfig::default().max_pov_size(1));
+	assert_no_collation(&collator, collator.genesis_head().to_vec());
+
+	// The dropped block isn't built upon by the next collation.
+	assert_eq!(block_on(collator.best_number()), 0);
+	assert_eq!(block_on(collator.state_entries()).len(), 1);
+}
+
+#[test]
+fn failing_block_yields_no_collation() {
+	let genesis =
+		GenesisConfig { state: u64::MAX, mode: ArithmeticMode::Checked, ..Default::default() };
+	let collator = Collator::from_config(CollatorConfig::default().genesis(genesis));
+	assert_no_collation(&collator, collator.genesis_head().to_vec());
+}
+
+/// The hash of the PoV the collation generation subsystem distributes for `pov`, which it
+/// compresses first if it is raw.
+fn distributed_pov_hash(pov: MaybeCompressedPoV) -> Hash {
+	match pov {
+		MaybeCompressedPoV::Compressed(pov) => pov.hash(),
+		MaybeCompressedPoV::Raw(pov) =>
+			sp_maybe_compressed_blob::compress(&pov.block_data.0, POV_BOMB_LIMIT)
+				.map_or(pov.hash(), |data| PoV { block_data: data.into() }.hash()),
+	}
+}
+
+#[test]
+fn seconded_pov_hash_is_the_one_distributed() {
+	let block = BlockData { ops: vec![Op::Add(1)], timestamp: 1, ..Default::default() };
+	let raw = PoV { block_data: block.encode().into() };
+
+	for compression in [PovCompression::None, PovCompression::Default, PovCompression::Zstd(19)] {
+		let (collation, pov_hash, _) =
+			build_collation(&block, &HeadData::default(), 1, compression);
+		assert_eq!(
+			distributed_pov_hash(collation.proof_of_validity),
+			pov_hash,
+			"{:?}",
+			compression
+		);
+	}
+
+	// Without compression, the raw PoV is distributed as is.
+	let (_, pov_hash, pov_size) =
+		build_collation(&block, &HeadData::default(), 1, PovCompression::None);
+	assert_eq!(pov_hash, raw.hash());
+	assert_eq!(pov_size.compressed, None);
+}
@@ -0,0 +1,76 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Helpers shared by the integration tests.
+
+// Every test crate compiles this module, but not all of them use every helper.
+#![allow(dead_code)]
+
+use futures::future::BoxFuture;
+use polkadot_primitives::{HeadData, PersistedValidationData};
+use sp_core::traits::SpawnNamed;
+use std::sync::{Arc, Mutex};
+
+/// A [`SpawnNamed`] that doesn't run anything, but keeps the spawned tasks around.
+#[derive(Clone, Default)]
+pub struct MockSpawner {
+	tasks: Arc<Mutex<Vec<(&'static str, BoxFuture<'static, ()>)>>>,
+}
+
+impl MockSpawner {
+	/// The names of the tasks spawned so far.
+	pub fn task_names(&self) -> Vec<&'static str> {
+		self.tasks.lock().unwrap().iter().map(|(name, _)| *name).collect()
+	}
+
+	/// Run the tasks spawned so far to completion.
+	pub fn run_tasks(&self) {
+		let tasks = std::mem::take(&mut *self.tasks.lock().unwrap());
+		for (_, task) in tasks {
+			futures::executor::block_on(task);
+		}
+	}
+}
+
+impl SpawnNamed for MockSpawner {
+	fn spawn_blocking(
+		&self,
+		name: &'static str,
+		_group: Option<&'static str>,
+		future: BoxFuture<'static, ()>,
+	) {
+		self.tasks.lock().unwrap().push((name, future));
+	}
+
+	fn spawn(
+		&self,
+		name: &'static str,
+		_group: Option<&'static str>,
+		future: BoxFuture<'static, ()>,
+	) {
+		self.tasks.lock().unwrap().push((name, future));
+	}
+}
+
+/// Validation data of a relay chain block building on `parent_head`.
+pub fn validation_data(parent_head: Vec<u8>) -> PersistedValidationData {
+	PersistedValidationData {
+		parent_head: HeadData(parent_head),
+		relay_parent_number: 1,
+		relay_parent_storage_root: Default::default(),
+		max_pov_size: polkadot_primitives::MAX_POV_SIZE,
+	}
+}
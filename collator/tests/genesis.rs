@@ -0,0 +1,45 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! JSON (de)serialization of the genesis configuration, as loaded from `--genesis-config`.
+
+use pba_parachain_collator::{ArithmeticMode, GenesisConfig, HashMode, StateCommitment};
+
+#[test]
+fn genesis_config_round_trips_through_json() {
+	let config = GenesisConfig {
+		state: 42,
+		add: Some(3),
+		mode: ArithmeticMode::Saturating,
+		hash_mode: HashMode::Sha256,
+		commitment: StateCommitment::Rolling,
+	};
+
+	let json = serde_json::to_string(&config).unwrap();
+	assert_eq!(serde_json::from_str::<GenesisConfig>(&json).unwrap(), config);
+}
+
+#[test]
+fn missing_fields_take_their_default() {
+	let config: GenesisConfig = serde_json::from_str(r#"{ "state": 42 }"#).unwrap();
+
+	assert_eq!(config, GenesisConfig { state: 42, ..Default::default() });
+}
+
+#[test]
+fn unknown_fields_are_rejected() {
+	assert!(serde_json::from_str::<GenesisConfig>(r#"{ "stat": 42 }"#).is_err());
+}
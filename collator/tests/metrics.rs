@@ -0,0 +1,91 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Checks the Prometheus metrics of the collator.
+
+mod common;
+
+use common::{validation_data, MockSpawner};
+use futures::executor::block_on;
+use pba_parachain_collator::Collator;
+use prometheus_endpoint::Registry;
+
+/// Create a collator whose metrics are registered in the returned registry.
+fn registered_collator() -> (Collator, Registry) {
+	let registry = Registry::new();
+	let mut collator = Collator::default();
+	block_on(collator.register_metrics(Some(&registry))).unwrap();
+	(collator, registry)
+}
+
+/// Get the value of the counter or gauge `name`.
+fn metric(registry: &Registry, name: &str) -> f64 {
+	let family = registry
+		.gather()
+		.into_iter()
+		.find(|family| family.get_name() == name)
+		.unwrap_or_else(|| panic!("{} is registered", name));
+	let metric = &family.get_metric()[0];
+	// Only the one matching the kind of the metric is set, the other one is zero.
+	metric.get_counter().get_value() + metric.get_gauge().get_value()
+}
+
+#[test]
+fn collations_produced_counts_every_collation() {
+	let (collator, registry) = registered_collator();
+	let collation_function = collator.create_collation_function(MockSpawner::default());
+	let data = validation_data(collator.genesis_head().to_vec());
+	assert_eq!(metric(&registry, "collations_produced_total"), 0.0);
+
+	for produced in 1..=2 {
+		assert!(block_on(collation_function(Default::default(), &data)).is_some());
+		assert_eq!(metric(&registry, "collations_produced_total"), produced as f64);
+	}
+}
+
+#[test]
+fn state_entries_follows_inserts_and_prunes() {
+	let (collator, registry) = registered_collator();
+	assert_eq!(metric(&registry, "para_state_entries"), 1.0);
+
+	block_on(async {
+		collator.advance_n(collator.best_head().await, 4).await.unwrap();
+		assert_eq!(metric(&registry, "para_state_entries"), 5.0);
+		// From the best head back to genesis.
+		let chain = collator.canonical_chain().await;
+		assert_eq!(collator.child_heads(&chain[4].hash()).await, vec![chain[3].hash()]);
+
+		collator.set_state_capacity(2).await;
+		assert_eq!(metric(&registry, "para_state_entries"), 2.0);
+		// Only the genesis and the best head are kept, the fork tree forgets the others.
+		for head in &chain[1..] {
+			assert_eq!(collator.child_heads(&head.hash()).await, Vec::<[u8; 32]>::new());
+		}
+	});
+}
+
+#[test]
+fn best_block_number_follows_every_advance() {
+	let (collator, registry) = registered_collator();
+	assert_eq!(metric(&registry, "para_best_block_number"), 0.0);
+
+	block_on(async {
+		for number in 1..=3 {
+			collator.advance_n(collator.best_head().await, 1).await.unwrap();
+			assert_eq!(metric(&registry, "para_best_block_number"), number as f64);
+		}
+	});
+}
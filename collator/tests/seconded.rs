@@ -0,0 +1,121 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Checks how the collator reacts to a seconded statement that doesn't match its collation.
+
+mod common;
+
+use common::{validation_data, MockSpawner};
+use futures::executor::block_on;
+use pba_parachain_collator::{Collator, CollatorConfig, SecondedMismatchPolicy};
+use polkadot_node_primitives::{CollationSecondedSignal, SignedFullStatement, Statement};
+use polkadot_primitives::{
+	CandidateCommitments, CandidateDescriptor, CollatorId, CollatorSignature,
+	CommittedCandidateReceipt, Hash, SigningContext, ValidatorIndex, ValidatorPair,
+};
+use sp_core::{sr25519, Pair};
+
+/// A seconded statement for a candidate with another PoV than any collation of ours.
+fn mismatched_signal(relay_parent: Hash) -> CollationSecondedSignal {
+	let descriptor = CandidateDescriptor {
+		para_id: 100.into(),
+		relay_parent,
+		collator: CollatorId::from(sr25519::Public::from_raw([0; 32])),
+		persisted_validation_data_hash: Default::default(),
+		pov_hash: Hash::repeat_byte(1),
+		erasure_root: Default::default(),
+		signature: CollatorSignature::from(sr25519::Signature([0; 64])),
+		para_head: Default::default(),
+		validation_code_hash: Hash::default().into(),
+	};
+	let statement = Statement::Seconded(CommittedCandidateReceipt {
+		descriptor,
+		commitments: CandidateCommitments::default(),
+	});
+
+	let validator = ValidatorPair::generate().0;
+	let context = SigningContext { session_index: 0, parent_hash: relay_parent };
+	let signature = validator.sign(&statement.to_compact().signing_payload(&context));
+	let statement = SignedFullStatement::new(
+		statement,
+		ValidatorIndex(0),
+		signature,
+		&context,
+		&validator.public(),
+	)
+	.expect("the statement is signed by the validator");
+
+	CollationSecondedSignal { relay_parent, statement }
+}
+
+/// Produce a collation with `collator`, have the relay chain second another candidate and run the
+/// task waiting for the seconded statement.
+fn second_mismatched_candidate(collator: &Collator) {
+	let spawner = MockSpawner::default();
+	let collation_function = collator.create_collation_function(spawner.clone());
+
+	let data = validation_data(collator.genesis_head().to_vec());
+	let result = block_on(collation_function(Default::default(), &data))
+		.expect("a collation is produced on top of genesis");
+	result
+		.result_sender
+		.expect("the collator waits for the seconded statement")
+		.send(mismatched_signal(Default::default()))
+		.expect("the collator listens for the seconded statement");
+
+	spawner.run_tasks();
+}
+
+#[test]
+fn lenient_collator_keeps_collating_after_a_mismatch() {
+	let collator = Collator::from_config(
+		CollatorConfig::default().seconded_mismatch_policy(SecondedMismatchPolicy::Lenient),
+	);
+
+	second_mismatched_candidate(&collator);
+
+	assert!(!collator.is_halted());
+	let collation_function = collator.create_collation_function(MockSpawner::default());
+	let data = validation_data(collator.genesis_head().to_vec());
+	assert!(block_on(collation_function(Default::default(), &data)).is_some());
+}
+
+#[test]
+fn strict_collator_halts_after_a_mismatch() {
+	let collator = Collator::from_config(
+		CollatorConfig::default().seconded_mismatch_policy(SecondedMismatchPolicy::Strict),
+	);
+
+	second_mismatched_candidate(&collator);
+
+	assert!(collator.is_halted());
+	let collation_function = collator.create_collation_function(MockSpawner::default());
+	let data = validation_data(collator.genesis_head().to_vec());
+	assert!(block_on(collation_function(Default::default(), &data)).is_none());
+
+	collator.resume();
+	assert!(!collator.is_halted());
+	assert!(block_on(collation_function(Default::default(), &data)).is_some());
+}
+
+#[test]
+fn collator_is_lenient_by_default() {
+	let collator = Collator::default();
+
+	second_mismatched_candidate(&collator);
+
+	assert!(!collator.is_halted());
+}
@@ -0,0 +1,55 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Checks the state queries of the collator.
+
+use futures::executor::block_on;
+use parity_scale_codec::{Decode, Encode};
+use pba_parachain_collator::{Collator, StateSnapshot};
+
+#[test]
+fn peek_advance_leaves_the_state_unchanged() {
+	block_on(async {
+		let collator = Collator::default();
+		let entries = collator.state_entries().await;
+
+		let (block_data, head) = collator.peek_advance(collator.best_head().await).await.unwrap();
+		assert_eq!(head.number, 1);
+		assert_eq!(block_data.state, entries[0].1);
+
+		assert_eq!(collator.state_entries().await, entries);
+		assert_eq!(collator.best_number().await, 0);
+		assert_eq!(collator.state_for(&head).await, None);
+	})
+}
+
+#[test]
+fn snapshot_round_trips_into_a_fresh_collator() {
+	block_on(async {
+		let collator = Collator::default();
+		let best = collator.advance_n(collator.best_head().await, 3).await.unwrap();
+
+		let encoded = collator.snapshot().await.encode();
+		let restored = Collator::default();
+		restored.restore(StateSnapshot::decode(&mut &encoded[..]).unwrap()).await;
+
+		assert_eq!(restored.best_head().await, best);
+		for (head, state) in collator.state_entries().await {
+			assert_eq!(restored.state_for(&head).await, Some(state));
+		}
+		assert_eq!(restored.snapshot().await, collator.snapshot().await);
+	})
+}
@@ -0,0 +1,27 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Decoding arbitrary bytes as [`BlockData`] must fail gracefully instead of panicking.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use parity_scale_codec::Decode;
+use pba_pvf::BlockData;
+
+fuzz_target!(|data: &[u8]| {
+	let _ = BlockData::decode(&mut &data[..]);
+});
@@ -0,0 +1,249 @@
+// Copyright 2019-2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Alternative state model where the state is a set of account balances.
+//!
+//! Instead of a single number, the state maps accounts to balances and blocks carry transfers
+//! between them, signed by the sender. The head commits to the hash of the encoded balances.
+//!
+//! Every transfer carries the nonce of its sender, which it increments, so that a signed transfer
+//! can be applied only once.
+//!
+//! The balances, like the extrinsics and events roots, are hashed with the [`HashMode`] of the
+//! chain, chosen at genesis.
+
+use crate::{
+	check_parent, events_root, ExecuteError, HashMode, HeadData, VersionedHeadData, MAX_BLOCK_SIZE,
+};
+use parity_scale_codec::{Decode, Encode};
+use sp_core::{sr25519, Pair as _};
+use sp_std::{collections::btree_map::BTreeMap, vec::Vec};
+
+/// Identifier of an account, the raw sr25519 public key of its owner.
+pub type AccountId = [u8; 32];
+
+/// The maximum number of transfers a single block may carry.
+pub const MAX_TRANSFERS_PER_BLOCK: usize = 128;
+
+/// The balance and nonce of an account.
+#[derive(Default, Clone, Copy, Encode, Decode, Debug, PartialEq, Eq)]
+pub struct Account {
+	/// The balance of the account.
+	pub balance: u64,
+	/// The number of transfers the account sent, the nonce of its next transfer.
+	pub nonce: u64,
+}
+
+/// The accounts of the chain. Accounts without an entry have a zero balance and nonce.
+#[derive(Default, Clone, Encode, Decode, Debug, PartialEq, Eq)]
+pub struct Balances(pub BTreeMap<AccountId, Account>);
+
+impl Balances {
+	/// Get the balance of `account`.
+	pub fn balance(&self, account: &AccountId) -> u64 {
+		self.account(account).balance
+	}
+
+	/// Get the nonce of the next transfer sent by `account`.
+	pub fn nonce(&self, account: &AccountId) -> u64 {
+		self.account(account).nonce
+	}
+
+	fn account(&self, account: &AccountId) -> Account {
+		self.0.get(account).copied().unwrap_or_default()
+	}
+
+	/// Hash the balances with `hash_mode`, the commitment stored in [`HeadData::post_state`].
+	pub fn hash(&self, hash_mode: HashMode) -> [u8; 32] {
+		hash_mode.hash(&self.encode())
+	}
+
+	/// Move `amount` from `from` to `to`, incrementing the nonce of `from`.
+	///
+	/// Accounts with a zero balance and nonce are removed so that the commitment doesn't depend on
+	/// them. Accounts that sent a transfer are kept, so their nonce never goes back.
+	fn transfer(&mut self, transfer: &Transfer) -> Result<(), ExecuteError> {
+		let mut from = self.account(&transfer.from);
+		if transfer.nonce != from.nonce {
+			return Err(ExecuteError::InvalidNonce)
+		}
+		from.nonce = from.nonce.checked_add(1).ok_or(ExecuteError::InvalidNonce)?;
+		from.balance = from.balance.checked_sub(transfer.amount).ok_or(ExecuteError::Overdraft)?;
+		self.set(transfer.from, from);
+
+		let mut to = self.account(&transfer.to);
+		to.balance =
+			to.balance.checked_add(transfer.amount).ok_or(ExecuteError::BalanceOverflow)?;
+		self.set(transfer.to, to);
+
+		Ok(())
+	}
+
+	fn set(&mut self, id: AccountId, account: Account) {
+		if account == Account::default() {
+			self.0.remove(&id);
+		} else {
+			self.0.insert(id, account);
+		}
+	}
+}
+
+/// A transfer of `amount` from `from` to `to`.
+#[derive(Clone, Encode, Decode, Debug, PartialEq, Eq)]
+pub struct Transfer {
+	/// The account to debit.
+	pub from: AccountId,
+	/// The account to credit.
+	pub to: AccountId,
+	/// The amount to move.
+	pub amount: u64,
+	/// The nonce of `from`, see [`Balances::nonce`].
+	///
+	/// This prevents applying the transfer twice, within a block and across blocks.
+	pub nonce: u64,
+}
+
+/// A [`Transfer`] signed by its sender.
+#[derive(Clone, Encode, Decode, Debug, PartialEq, Eq)]
+pub struct SignedTransfer {
+	/// The transfer to apply.
+	pub transfer: Transfer,
+	/// Number of the only block the transfer can be included in.
+	///
+	/// This prevents replaying the transfer in later blocks.
+	pub block_number: u64,
+	/// The sr25519 signature of `(transfer, block_number)` by `transfer.from`.
+	pub signature: [u8; 64],
+}
+
+impl SignedTransfer {
+	/// The payload the sender signs.
+	pub fn signing_payload(transfer: &Transfer, block_number: u64) -> Vec<u8> {
+		(transfer, block_number).encode()
+	}
+
+	/// Sign `transfer` for inclusion in block `block_number`.
+	///
+	/// The key of `pair` must match `transfer.from` for the transfer to be valid.
+	#[cfg(feature = "std")]
+	pub fn sign(transfer: Transfer, block_number: u64, pair: &sr25519::Pair) -> Self {
+		let signature = pair.sign(&Self::signing_payload(&transfer, block_number)).0;
+		Self { transfer, block_number, signature }
+	}
+
+	/// Check that the transfer was signed by its sender for block `block_number`.
+	fn verify(&self, block_number: u64) -> bool {
+		self.block_number == block_number &&
+			sr25519::Pair::verify(
+				&sr25519::Signature::from_raw(self.signature),
+				Self::signing_payload(&self.transfer, self.block_number),
+				&sr25519::Public::from_raw(self.transfer.from),
+			)
+	}
+}
+
+/// Block data for the balances model.
+#[derive(Default, Clone, Encode, Decode, Debug)]
+pub struct TransferBlockData {
+	/// Balances to begin from.
+	pub balances: Balances,
+	/// Signed transfers to apply, in order.
+	pub transfers: Vec<SignedTransfer>,
+}
+
+impl TransferBlockData {
+	/// Apply the block's transfers to `balances` in order, returning the post-state.
+	///
+	/// Fails with [`ExecuteError::Overdraft`] if an account sends more than it holds, with
+	/// [`ExecuteError::BalanceOverflow`] if an account receives more than it can hold, and with
+	/// [`ExecuteError::InvalidNonce`] if a transfer doesn't carry the nonce of its sender.
+	///
+	/// Signatures are not checked, see [`execute`].
+	pub fn apply(&self) -> Result<Balances, ExecuteError> {
+		let mut balances = self.balances.clone();
+		for signed in &self.transfers {
+			balances.transfer(&signed.transfer)?;
+		}
+		Ok(balances)
+	}
+}
+
+/// Get the genesis head of a chain starting with the given `balances`.
+pub fn genesis_head(balances: &Balances) -> HeadData {
+	genesis_head_with(balances, HashMode::default())
+}
+
+/// Same as [`genesis_head`], but for a chain hashing with `hash_mode`.
+pub fn genesis_head_with(balances: &Balances, hash_mode: HashMode) -> HeadData {
+	HeadData {
+		number: 0,
+		parent_hash: Default::default(),
+		post_state: balances.hash(hash_mode),
+		extrinsics_root: Default::default(),
+		events_root: Default::default(),
+		timestamp: 0,
+		hash_mode,
+		history: None,
+	}
+}
+
+/// Execute a block of transfers on top of given parent head, producing new parent head
+/// if valid.
+///
+/// See [`crate::execute`] for the versions the parent head may be of.
+///
+/// The whole block is rejected if any transfer is not correctly signed by its sender.
+pub fn execute(
+	parent_hash: [u8; 32],
+	parent_head: impl Into<VersionedHeadData>,
+	block_data: &TransferBlockData,
+) -> Result<HeadData, ExecuteError> {
+	let parent_head = check_parent(parent_hash, parent_head.into())?;
+
+	let number = parent_head.number.checked_add(1).ok_or(ExecuteError::BlockNumberOverflow)?;
+
+	if block_data.transfers.len() > MAX_TRANSFERS_PER_BLOCK {
+		return Err(ExecuteError::TooManyTransfers)
+	}
+
+	if block_data.encoded_size() > MAX_BLOCK_SIZE {
+		return Err(ExecuteError::BlockTooLarge)
+	}
+
+	let hash_mode = parent_head.hash_mode;
+	if block_data.balances.hash(hash_mode) != parent_head.post_state {
+		return Err(ExecuteError::StateMismatch)
+	}
+
+	if !block_data.transfers.iter().all(|signed| signed.verify(number)) {
+		return Err(ExecuteError::InvalidSignature)
+	}
+
+	let new_balances = block_data.apply()?;
+
+	Ok(HeadData {
+		number,
+		parent_hash,
+		post_state: new_balances.hash(hash_mode),
+		extrinsics_root: hash_mode.hash(&block_data.encode()),
+		events_root: events_root(hash_mode, &[]),
+		// Transfer blocks don't carry a timestamp.
+		timestamp: parent_head.timestamp,
+		hash_mode,
+		// Transfers are not folded into a history.
+		history: None,
+	})
+}
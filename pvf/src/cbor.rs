@@ -0,0 +1,112 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! CBOR interchange format of the head data.
+//!
+//! Only meant for talking to external tooling: the SCALE encoding stays the canonical one, which
+//! is what gets hashed.
+
+use crate::{HashMode, HeadData};
+use alloc::string::{String, ToString};
+use ciborium::value::Value;
+use sp_std::vec::Vec;
+
+/// Errors that can occur while decoding a [`HeadData`] from CBOR.
+#[derive(Debug)]
+pub enum CborError {
+	/// The input is not valid CBOR.
+	Malformed(String),
+	/// The given field of the head is missing or of the wrong type.
+	InvalidField(&'static str),
+}
+
+impl HeadData {
+	/// Encode the head as a CBOR map, with the hashes as byte strings.
+	pub fn to_cbor(&self) -> Vec<u8> {
+		let bytes = |hash: &[u8; 32]| Value::Bytes(hash.to_vec());
+		let value = Value::Map(sp_std::vec![
+			(Value::Text("number".into()), Value::Integer(self.number.into())),
+			(Value::Text("parent_hash".into()), bytes(&self.parent_hash)),
+			(Value::Text("post_state".into()), bytes(&self.post_state)),
+			(Value::Text("extrinsics_root".into()), bytes(&self.extrinsics_root)),
+			(Value::Text("events_root".into()), bytes(&self.events_root)),
+			(Value::Text("timestamp".into()), Value::Integer(self.timestamp.into())),
+			(Value::Text("hash_mode".into()), Value::Text(hash_mode_name(self.hash_mode).into())),
+			(Value::Text("history".into()), self.history.as_ref().map_or(Value::Null, bytes)),
+		]);
+
+		let mut out = Vec::new();
+		ciborium::ser::into_writer(&value, &mut out).expect("Writing to a `Vec` doesn't fail");
+		out
+	}
+
+	/// Decode a head encoded with [`Self::to_cbor`].
+	///
+	/// Fails if anything follows the encoded head.
+	pub fn from_cbor(mut bytes: &[u8]) -> Result<Self, CborError> {
+		let value: Value = ciborium::de::from_reader(&mut bytes)
+			.map_err(|e| CborError::Malformed(e.to_string()))?;
+		if !bytes.is_empty() {
+			return Err(CborError::Malformed(format!("{} trailing bytes", bytes.len())))
+		}
+		let map = value.as_map().ok_or(CborError::Malformed("expected a map".into()))?;
+		let field = |name: &'static str| {
+			map.iter()
+				.find(|(key, _)| key.as_text() == Some(name))
+				.map(|(_, value)| value)
+				.ok_or(CborError::InvalidField(name))
+		};
+		let int = |name| {
+			field(name)?
+				.as_integer()
+				.and_then(|i| u64::try_from(i).ok())
+				.ok_or(CborError::InvalidField(name))
+		};
+		let hash = |name| {
+			field(name)?
+				.as_bytes()
+				.and_then(|b| <[u8; 32]>::try_from(&b[..]).ok())
+				.ok_or(CborError::InvalidField(name))
+		};
+
+		Ok(Self {
+			number: int("number")?,
+			parent_hash: hash("parent_hash")?,
+			post_state: hash("post_state")?,
+			extrinsics_root: hash("extrinsics_root")?,
+			events_root: hash("events_root")?,
+			timestamp: int("timestamp")?,
+			hash_mode: match field("hash_mode")?.as_text() {
+				Some("blake3") => HashMode::Blake3,
+				Some("keccak256") => HashMode::Keccak256,
+				Some("sha256") => HashMode::Sha256,
+				_ => return Err(CborError::InvalidField("hash_mode")),
+			},
+			history: match field("history")? {
+				Value::Null => None,
+				_ => Some(hash("history")?),
+			},
+		})
+	}
+}
+
+fn hash_mode_name(mode: HashMode) -> &'static str {
+	match mode {
+		HashMode::Blake3 => "blake3",
+		HashMode::Keccak256 => "keccak256",
+		HashMode::Sha256 => "sha256",
+	}
+}
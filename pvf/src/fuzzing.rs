@@ -0,0 +1,74 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! [`Arbitrary`] implementations of the block and head data, for fuzzing.
+//!
+//! Values at the boundaries are generated more often than by chance, as they are where the
+//! interesting bugs are.
+
+use crate::{BlockData, HeadData, MAX_OPS_PER_BLOCK};
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+/// An arbitrary number, which is `0` or `u64::MAX` half of the time.
+fn boundary_u64(u: &mut Unstructured) -> Result<u64> {
+	Ok(match u.int_in_range(0..=3u8)? {
+		0 => 0,
+		1 => u64::MAX,
+		_ => u.arbitrary()?,
+	})
+}
+
+/// An arbitrary hash, which is zeroed a quarter of the time.
+fn hash(u: &mut Unstructured) -> Result<[u8; 32]> {
+	if u.ratio(1u8, 4)? {
+		Ok([0; 32])
+	} else {
+		u.arbitrary()
+	}
+}
+
+impl<'a> Arbitrary<'a> for HeadData {
+	fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+		Ok(Self {
+			number: boundary_u64(u)?,
+			parent_hash: hash(u)?,
+			post_state: hash(u)?,
+			extrinsics_root: hash(u)?,
+			events_root: hash(u)?,
+			timestamp: boundary_u64(u)?,
+			hash_mode: u.arbitrary()?,
+			history: if u.arbitrary()? { Some(hash(u)?) } else { None },
+		})
+	}
+}
+
+impl<'a> Arbitrary<'a> for BlockData {
+	fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+		// Up to one operation too many, to also hit the limit.
+		let num_ops = u.int_in_range(0..=MAX_OPS_PER_BLOCK + 1)?;
+
+		Ok(Self {
+			state: boundary_u64(u)?,
+			ops: (0..num_ops).map(|_| u.arbitrary()).collect::<Result<_>>()?,
+			upward_messages: u.arbitrary()?,
+			horizontal_messages: u.arbitrary()?,
+			processed_downward_messages: u.arbitrary()?,
+			new_validation_code: u.arbitrary()?,
+			timestamp: boundary_u64(u)?,
+			mode: u.arbitrary()?,
+		})
+	}
+}
@@ -0,0 +1,63 @@
+// Copyright 2019-2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Hash functions used for the state and head commitments.
+
+/// A 256-bit hash function.
+pub trait Hasher {
+	/// Hash the given data.
+	fn hash(data: &[u8]) -> [u8; 32];
+}
+
+/// The BLAKE3 hash function.
+pub struct Blake3;
+
+impl Hasher for Blake3 {
+	fn hash(data: &[u8]) -> [u8; 32] {
+		blake3::hash(data).into()
+	}
+}
+
+/// The Keccak-256 hash function, as used by Ethereum.
+pub struct Keccak256;
+
+impl Hasher for Keccak256 {
+	fn hash(data: &[u8]) -> [u8; 32] {
+		use tiny_keccak::Hasher as _;
+
+		let mut keccak = tiny_keccak::Keccak::v256();
+		keccak.update(data);
+		let mut out = [0u8; 32];
+		keccak.finalize(&mut out);
+		out
+	}
+}
+
+/// The SHA-256 hash function.
+pub struct Sha256;
+
+impl Hasher for Sha256 {
+	fn hash(data: &[u8]) -> [u8; 32] {
+		use sha2::Digest as _;
+
+		sha2::Sha256::digest(data).into()
+	}
+}
+
+/// The hasher used by the chain unless configured otherwise, see [`crate::HashMode`].
+///
+/// The collator and the validation code must agree on it, otherwise every block is invalid.
+pub type DefaultHasher = Blake3;
@@ -19,11 +19,32 @@
 #![no_std]
 #![cfg_attr(not(feature = "std"), feature(core_intrinsics, lang_items, alloc_error_handler))]
 
-use parity_scale_codec::{Decode, Encode};
+use parity_scale_codec::{Decode, Encode, Input, Output};
+use sp_std::vec::Vec;
 
+extern crate alloc;
+
+#[cfg(feature = "balances")]
+pub mod balances;
+#[cfg(feature = "cbor")]
+mod cbor;
+#[cfg(feature = "fuzzing")]
+mod fuzzing;
+mod hasher;
+mod log_hook;
+#[cfg(feature = "merkle")]
+pub mod merkle;
+#[cfg(feature = "serde")]
+mod serde_hex;
 #[cfg(not(feature = "std"))]
 mod validate_block;
 
+#[cfg(feature = "cbor")]
+pub use cbor::CborError;
+pub use hasher::{Blake3, DefaultHasher, Hasher, Keccak256, Sha256};
+#[cfg(feature = "log-hook")]
+pub use log_hook::{LogHook, NoopLog};
+
 // #[cfg(not(feature = "std"))]
 // #[global_allocator]
 // static ALLOC: dlmalloc::GlobalDlmalloc = dlmalloc::GlobalDlmalloc;
@@ -41,58 +62,643 @@ pub fn wasm_binary_unwrap() -> &'static [u8] {
 	)
 }
 
+/// Version of the [`HeadData`] encoding, encoded as the first byte of every head.
+///
+/// Bumped whenever the layout of the head changes so that heads of an unknown version fail to
+/// decode instead of being silently misinterpreted. Heads of the previous versions that are still
+/// supported are migrated on decoding, see [`VersionedHeadData`].
+pub const HEAD_DATA_VERSION: u8 = 5;
+
+/// The hash function of a chain's heads and states.
+///
+/// The mode is chosen at genesis and carried over by every head, so the collator and the
+/// validation code always agree on it.
+#[derive(Default, Clone, Copy, Hash, Encode, Decode, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+pub enum HashMode {
+	/// Hash with [`Blake3`].
+	#[default]
+	Blake3,
+	/// Hash with [`Keccak256`], which EVM chains can verify cheaply.
+	Keccak256,
+	/// Hash with [`Sha256`].
+	Sha256,
+}
+
+impl HashMode {
+	/// Hash `data` with the hash function of this mode.
+	pub fn hash(self, data: &[u8]) -> [u8; 32] {
+		match self {
+			HashMode::Blake3 => Blake3::hash(data),
+			HashMode::Keccak256 => Keccak256::hash(data),
+			HashMode::Sha256 => Sha256::hash(data),
+		}
+	}
+
+	/// Hash `state` of a chain in the given arithmetic `mode` with the hash function of this mode.
+	pub fn hash_state(self, state: u64, mode: ArithmeticMode) -> [u8; 32] {
+		match self {
+			HashMode::Blake3 => hash_state_with::<Blake3>(state, mode),
+			HashMode::Keccak256 => hash_state_with::<Keccak256>(state, mode),
+			HashMode::Sha256 => hash_state_with::<Sha256>(state, mode),
+		}
+	}
+}
+
 /// Head data for this parachain.
-#[derive(Default, Clone, Hash, Eq, PartialEq, Encode, Decode, Debug)]
+#[derive(Default, Clone, Hash, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct HeadData {
 	/// Block number.
 	pub number: u64,
 	/// Parent block hash.
+	#[cfg_attr(feature = "serde", serde(with = "serde_hex"))]
 	pub parent_hash: [u8; 32],
 	/// Post-execution state hash.
+	#[cfg_attr(feature = "serde", serde(with = "serde_hex"))]
 	pub post_state: [u8; 32],
+	/// Hash of the encoded block data that produced this head, with its [`HashMode`].
+	///
+	/// Zero for the genesis head.
+	#[cfg_attr(feature = "serde", serde(with = "serde_hex"))]
+	pub extrinsics_root: [u8; 32],
+	/// Hash of the encoded events emitted while producing this head, see [`events_root`].
+	///
+	/// Zero for the genesis head.
+	#[cfg_attr(feature = "serde", serde(with = "serde_hex"))]
+	pub events_root: [u8; 32],
+	/// Timestamp of the block.
+	///
+	/// Zero for the genesis head.
+	pub timestamp: u64,
+	/// Hash function of the head and of its `post_state`, the same for all heads of a chain.
+	pub hash_mode: HashMode,
+	/// Rolling hash of all the operations applied since genesis, if the chain commits to them.
+	///
+	/// See [`StateCommitment::Rolling`].
+	#[cfg_attr(feature = "serde", serde(with = "serde_hex::option"))]
+	pub history: Option<[u8; 32]>,
+}
+
+impl Encode for HeadData {
+	fn size_hint(&self) -> usize {
+		1 + 8 + 4 * 32 + 8 + 1 + 33
+	}
+
+	fn encode_to<T: Output + ?Sized>(&self, dest: &mut T) {
+		HEAD_DATA_VERSION.encode_to(dest);
+		self.number.encode_to(dest);
+		self.parent_hash.encode_to(dest);
+		self.post_state.encode_to(dest);
+		self.extrinsics_root.encode_to(dest);
+		self.events_root.encode_to(dest);
+		self.timestamp.encode_to(dest);
+		self.hash_mode.encode_to(dest);
+		self.history.encode_to(dest);
+	}
+}
+
+/// Heads of older versions are migrated to the current one, see [`VersionedHeadData`].
+impl Decode for HeadData {
+	fn decode<I: Input>(input: &mut I) -> Result<Self, parity_scale_codec::Error> {
+		VersionedHeadData::decode(input).map(VersionedHeadData::into_latest)
+	}
+}
+
+/// Head data of version 4, before the [`HeadData::history`] was introduced.
+#[derive(Default, Clone, Hash, Eq, PartialEq, Debug, Encode, Decode)]
+pub struct HeadDataV4 {
+	/// Block number.
+	pub number: u64,
+	/// Parent block hash.
+	pub parent_hash: [u8; 32],
+	/// Post-execution state hash.
+	pub post_state: [u8; 32],
+	/// Hash of the encoded block data that produced this head.
+	pub extrinsics_root: [u8; 32],
+	/// Hash of the encoded events emitted while producing this head.
+	pub events_root: [u8; 32],
+	/// Timestamp of the block.
+	pub timestamp: u64,
+	/// Hash function of the head and of its `post_state`.
+	pub hash_mode: HashMode,
+}
+
+impl HeadDataV4 {
+	/// Migrate the head to the current version.
+	///
+	/// Chains of this version predate [`StateCommitment::Rolling`], so the head has no history.
+	pub fn migrate(self) -> HeadData {
+		HeadData {
+			number: self.number,
+			parent_hash: self.parent_hash,
+			post_state: self.post_state,
+			extrinsics_root: self.extrinsics_root,
+			events_root: self.events_root,
+			timestamp: self.timestamp,
+			hash_mode: self.hash_mode,
+			history: None,
+		}
+	}
+}
+
+/// A head in any of the supported versions of the encoding.
+///
+/// Encoded as its version, see [`HEAD_DATA_VERSION`], followed by the head, so the encoding of
+/// [`VersionedHeadData::V5`] is the one of the [`HeadData`] it wraps.
+///
+/// Migrating a head changes its encoding and hence its hash. The children of a head refer to the
+/// hash of its encoding in its own version, i.e. of the bytes the relay chain stores for it, see
+/// [`VersionedHeadData::hash`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VersionedHeadData {
+	/// A head of version 4.
+	V4(HeadDataV4),
+	/// A head of the current version.
+	V5(HeadData),
+}
+
+impl VersionedHeadData {
+	/// The version of the head.
+	pub fn version(&self) -> u8 {
+		match self {
+			VersionedHeadData::V4(_) => 4,
+			VersionedHeadData::V5(_) => HEAD_DATA_VERSION,
+		}
+	}
+
+	/// The hash function of the head.
+	pub fn hash_mode(&self) -> HashMode {
+		match self {
+			VersionedHeadData::V4(head) => head.hash_mode,
+			VersionedHeadData::V5(head) => head.hash_mode,
+		}
+	}
+
+	/// Hash the head, as encoded in its own version, with the hash function of its [`HashMode`].
+	///
+	/// This is the hash the children of the head refer to. Same as [`HeadData::hash`] for a head of
+	/// the current version.
+	pub fn hash(&self) -> [u8; 32] {
+		self.hash_mode().hash(&self.encode())
+	}
+
+	/// Migrate the head to the current version.
+	pub fn into_latest(self) -> HeadData {
+		match self {
+			VersionedHeadData::V4(head) => head.migrate(),
+			VersionedHeadData::V5(head) => head,
+		}
+	}
+}
+
+impl From<HeadData> for VersionedHeadData {
+	fn from(head: HeadData) -> Self {
+		VersionedHeadData::V5(head)
+	}
+}
+
+impl Encode for VersionedHeadData {
+	fn size_hint(&self) -> usize {
+		match self {
+			VersionedHeadData::V4(head) => 1 + head.size_hint(),
+			VersionedHeadData::V5(head) => head.size_hint(),
+		}
+	}
+
+	fn encode_to<T: Output + ?Sized>(&self, dest: &mut T) {
+		match self {
+			VersionedHeadData::V4(head) => {
+				4u8.encode_to(dest);
+				head.encode_to(dest);
+			},
+			VersionedHeadData::V5(head) => head.encode_to(dest),
+		}
+	}
+}
+
+impl Decode for VersionedHeadData {
+	fn decode<I: Input>(input: &mut I) -> Result<Self, parity_scale_codec::Error> {
+		match u8::decode(input)? {
+			4 => Ok(VersionedHeadData::V4(Decode::decode(input)?)),
+			HEAD_DATA_VERSION => Ok(VersionedHeadData::V5(HeadData {
+				number: Decode::decode(input)?,
+				parent_hash: Decode::decode(input)?,
+				post_state: Decode::decode(input)?,
+				extrinsics_root: Decode::decode(input)?,
+				events_root: Decode::decode(input)?,
+				timestamp: Decode::decode(input)?,
+				hash_mode: Decode::decode(input)?,
+				history: Decode::decode(input)?,
+			})),
+			_ => Err("Unsupported HeadData version".into()),
+		}
+	}
+}
+
+/// What the `post_state` of a head commits to, chosen at genesis.
+#[derive(Default, Clone, Copy, Encode, Decode, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+pub enum StateCommitment {
+	/// The hash of the state, see [`hash_state`].
+	#[default]
+	Value,
+	/// The hash of the state and of the [`HeadData::history`] of all the applied operations.
+	///
+	/// Every operation is folded into the history as `hash(history ++ op.encode())`.
+	Rolling,
+}
+
+/// The maximum number of operations a single block may carry.
+pub const MAX_OPS_PER_BLOCK: usize = 128;
+
+/// The maximum amount a single block may add to the state, over all its [`Op::Add`] operations.
+///
+/// Part of the validation code registered at genesis, so all validators enforce the same limit.
+pub const MAX_ADD_PER_BLOCK: u64 = 1 << 32;
+
+/// The maximum size of an encoded block, in bytes.
+///
+/// Matches the relay chain's maximum PoV size, so that the limit is enforced by the validation
+/// logic itself rather than only by the collator.
+pub const MAX_BLOCK_SIZE: usize = 5 * 1024 * 1024;
+
+/// How operations behave on overflow.
+///
+/// The mode is chosen at genesis and committed to in every state hash, see [`hash_state`], so a
+/// block can't switch to another mode.
+#[derive(Default, Clone, Copy, Encode, Decode, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+pub enum ArithmeticMode {
+	/// Wrap around at the boundaries of `u64`.
+	#[default]
+	Wrapping,
+	/// Reject the block with [`ExecuteError::Overflow`].
+	Checked,
+	/// Clamp at `0` and `u64::MAX`.
+	Saturating,
+}
+
+/// A single state transition operation.
+///
+/// How operations behave on overflow depends on the [`ArithmeticMode`] of the chain.
+#[derive(Clone, Copy, Encode, Decode, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+pub enum Op {
+	/// Add the given amount.
+	Add(u64),
+	/// Subtract the given amount.
+	Sub(u64),
+	/// Multiply by the given amount.
+	Mul(u64),
+}
+
+impl Op {
+	/// Apply the operation to `state`.
+	pub fn apply(&self, state: u64) -> u64 {
+		match *self {
+			Op::Add(x) => state.wrapping_add(x),
+			Op::Sub(x) => state.wrapping_sub(x),
+			Op::Mul(x) => state.wrapping_mul(x),
+		}
+	}
+
+	/// Apply the operation to `state`, returning `None` on overflow.
+	pub fn checked_apply(&self, state: u64) -> Option<u64> {
+		match *self {
+			Op::Add(x) => state.checked_add(x),
+			Op::Sub(x) => state.checked_sub(x),
+			Op::Mul(x) => state.checked_mul(x),
+		}
+	}
+
+	/// Apply the operation to `state`, saturating at the numeric bounds.
+	pub fn saturating_apply(&self, state: u64) -> u64 {
+		match *self {
+			Op::Add(x) => state.saturating_add(x),
+			Op::Sub(x) => state.saturating_sub(x),
+			Op::Mul(x) => state.saturating_mul(x),
+		}
+	}
+}
+
+/// A message sent to another parachain over an HRMP channel.
+#[derive(Clone, Encode, Decode, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+pub struct HorizontalMessage {
+	/// Id of the receiving parachain.
+	pub recipient: u32,
+	/// The message payload.
+	pub data: Vec<u8>,
 }
 
 /// Block data for this parachain.
 #[derive(Default, Clone, Encode, Decode, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BlockData {
 	/// State to begin from.
 	pub state: u64,
-	/// Amount to add (wrapping).
-	pub add: u64,
+	/// Operations to apply, in order.
+	pub ops: Vec<Op>,
+	/// Messages sent to the relay chain by this block.
+	pub upward_messages: Vec<Vec<u8>>,
+	/// Messages sent to other parachains by this block, sorted by recipient.
+	pub horizontal_messages: Vec<HorizontalMessage>,
+	/// Number of downward messages processed by this block.
+	pub processed_downward_messages: u32,
+	/// New validation code scheduled by this block, if any.
+	pub new_validation_code: Option<Vec<u8>>,
+	/// Timestamp of the block, strictly greater than the one of its parent.
+	pub timestamp: u64,
+	/// Arithmetic mode of the chain, which must match the one `state` was hashed with.
+	pub mode: ArithmeticMode,
 }
 
+impl BlockData {
+	/// Apply the block's operations to `state` in order, returning the post-state.
+	///
+	/// Fails with [`ExecuteError::Overflow`] if an operation overflows in
+	/// [`ArithmeticMode::Checked`].
+	pub fn apply(&self) -> Result<u64, ExecuteError> {
+		match self.mode {
+			ArithmeticMode::Wrapping =>
+				Ok(self.ops.iter().fold(self.state, |state, op| op.apply(state))),
+			ArithmeticMode::Checked => self
+				.ops
+				.iter()
+				.try_fold(self.state, |state, op| op.checked_apply(state))
+				.ok_or(ExecuteError::Overflow),
+			ArithmeticMode::Saturating =>
+				Ok(self.ops.iter().fold(self.state, |state, op| op.saturating_apply(state))),
+		}
+	}
+
+	/// Total amount added by the block's [`Op::Add`] operations, saturating at `u64::MAX`.
+	pub fn total_add(&self) -> u64 {
+		self.ops
+			.iter()
+			.map(|op| match op {
+				Op::Add(x) => *x,
+				Op::Sub(_) | Op::Mul(_) => 0,
+			})
+			.fold(0, u64::saturating_add)
+	}
+}
+
+/// Something that happened while executing a block.
+#[derive(Clone, Encode, Decode, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Event {
+	/// The operations of the block changed the state.
+	StateChanged {
+		/// State before the block.
+		from: u64,
+		/// State after the block.
+		to: u64,
+	},
+}
+
+/// Commitment to the `events` of a block of a chain hashing with `hash_mode`, stored in
+/// [`HeadData::events_root`].
+pub fn events_root(hash_mode: HashMode, events: &[Event]) -> [u8; 32] {
+	hash_mode.hash(&events.encode())
+}
+
+/// Hash `data` with the [`DefaultHasher`].
 pub fn hash(data: &[u8]) -> [u8; 32] {
-	blake3::hash(data).into()
+	DefaultHasher::hash(data)
+}
+
+/// Hash `state` of a chain in the given `mode` with the [`DefaultHasher`].
+///
+/// See [`HashMode::hash_state`] for chains configured with another hasher.
+pub fn hash_state(state: u64, mode: ArithmeticMode) -> [u8; 32] {
+	hash_state_with::<DefaultHasher>(state, mode)
 }
 
-pub fn hash_state(state: u64) -> [u8; 32] {
-	hash(state.encode().as_slice())
+/// Hash `state` of a chain in the given `mode` with the given hasher.
+pub fn hash_state_with<H: Hasher>(state: u64, mode: ArithmeticMode) -> [u8; 32] {
+	H::hash((state, mode).encode().as_slice())
 }
 
 impl HeadData {
+	/// Hash the head with the hash function of its [`HashMode`].
+	///
+	/// This is the hash of the current encoding, also for a head decoded from an older one, whose
+	/// children refer to [`VersionedHeadData::hash`] instead.
 	pub fn hash(&self) -> [u8; 32] {
-		hash(&self.encode())
+		self.hash_mode.hash(&self.encode())
+	}
+
+	/// Hash the head with the given hasher.
+	pub fn hash_with<H: Hasher>(&self) -> [u8; 32] {
+		H::hash(&self.encode())
+	}
+
+	/// Check that the `post_state` of the head commits to `state` in the given `mode`.
+	pub fn commits_to(&self, state: u64, mode: ArithmeticMode) -> bool {
+		self.post_state == commit_state(self.hash_mode, self.history.as_ref(), state, mode)
+	}
+}
+
+/// Commitment to `state` in the given `mode`, along with the `history` of operations if any.
+fn commit_state(
+	hash_mode: HashMode,
+	history: Option<&[u8; 32]>,
+	state: u64,
+	mode: ArithmeticMode,
+) -> [u8; 32] {
+	let state_hash = hash_mode.hash_state(state, mode);
+	match history {
+		Some(history) => hash_mode.hash(&(history, state_hash).encode()),
+		None => state_hash,
+	}
+}
+
+/// Fold `ops` into the rolling `history` of the operations of a chain.
+pub fn fold_history(hash_mode: HashMode, history: [u8; 32], ops: &[Op]) -> [u8; 32] {
+	ops.iter().fold(history, |history, op| hash_mode.hash(&(history, op).encode()))
+}
+
+/// Get the genesis head of a chain whose state starts at `state`, applying operations in `mode`.
+pub fn genesis_head(state: u64, mode: ArithmeticMode) -> HeadData {
+	genesis_head_with(state, mode, HashMode::default(), StateCommitment::default())
+}
+
+/// Same as [`genesis_head`], but for a chain hashing with `hash_mode` and committing to its state
+/// as given by `commitment`.
+///
+/// The history of a [`StateCommitment::Rolling`] chain starts out zeroed.
+pub fn genesis_head_with(
+	state: u64,
+	mode: ArithmeticMode,
+	hash_mode: HashMode,
+	commitment: StateCommitment,
+) -> HeadData {
+	let history = match commitment {
+		StateCommitment::Value => None,
+		StateCommitment::Rolling => Some([0; 32]),
+	};
+
+	HeadData {
+		number: 0,
+		parent_hash: Default::default(),
+		post_state: commit_state(hash_mode, history.as_ref(), state, mode),
+		extrinsics_root: Default::default(),
+		events_root: Default::default(),
+		timestamp: 0,
+		hash_mode,
+		history,
 	}
 }
 
-/// Start state mismatched with parent header's state hash.
-#[derive(Debug)]
-pub struct StateMismatch;
+/// Errors that can occur while executing a block.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExecuteError {
+	/// The given parent hash doesn't match the hash of the parent head.
+	ParentHashMismatch,
+	/// Start state or arithmetic mode mismatched with parent header's state hash.
+	StateMismatch,
+	/// The block carries more than [`MAX_OPS_PER_BLOCK`] operations.
+	TooManyOps,
+	/// The encoded block is larger than [`MAX_BLOCK_SIZE`].
+	BlockTooLarge,
+	/// The block adds more than [`MAX_ADD_PER_BLOCK`] in total.
+	AddTooLarge,
+	/// The timestamp of the block is not greater than the one of its parent.
+	TimestampNotIncreasing,
+	/// An operation overflowed in [`ArithmeticMode::Checked`].
+	Overflow,
+	/// The parent head is at `u64::MAX`, so the block number can't advance.
+	BlockNumberOverflow,
+	/// A transfer debits more than the sender's balance.
+	#[cfg(feature = "balances")]
+	Overdraft,
+	/// A transfer credits more than the recipient's balance can hold.
+	#[cfg(feature = "balances")]
+	BalanceOverflow,
+	/// A transfer is not correctly signed by its sender.
+	#[cfg(feature = "balances")]
+	InvalidSignature,
+	/// The block carries more than [`balances::MAX_TRANSFERS_PER_BLOCK`] transfers.
+	#[cfg(feature = "balances")]
+	TooManyTransfers,
+	/// A transfer doesn't carry the nonce of its sender, e.g. because it was already applied.
+	#[cfg(feature = "balances")]
+	InvalidNonce,
+	/// The block carries more than [`merkle::MAX_UPDATES_PER_BLOCK`] updates.
+	#[cfg(feature = "merkle")]
+	TooManyUpdates,
+}
+
+/// Check that `parent_hash` is the hash of `parent_head` as encoded in its own version, see
+/// [`VersionedHeadData::hash`], and migrate the head to the current version.
+pub(crate) fn check_parent(
+	parent_hash: [u8; 32],
+	parent_head: VersionedHeadData,
+) -> Result<HeadData, ExecuteError> {
+	if parent_hash != parent_head.hash() {
+		return Err(ExecuteError::ParentHashMismatch)
+	}
+
+	Ok(parent_head.into_latest())
+}
 
 /// Execute a block body on top of given parent head, producing new parent head
 /// if valid.
+///
+/// The parent head may be of any supported version, `parent_hash` being the hash of its encoding
+/// in that version, see [`VersionedHeadData::hash`].
+///
+/// Every check, including the one of `parent_hash`, fails with an [`ExecuteError`] rather than an
+/// assertion, so debug and release builds reject the same blocks the same way.
+///
+/// With the `log-hook` feature, debug messages are sent to the host when running in Wasm.
 pub fn execute(
 	parent_hash: [u8; 32],
-	parent_head: HeadData,
+	parent_head: impl Into<VersionedHeadData>,
+	block_data: &BlockData,
+) -> Result<HeadData, ExecuteError> {
+	execute_with_events(parent_hash, parent_head, block_data).map(|(head, _)| head)
+}
+
+/// Same as [`execute`], but also returns the events emitted by the block.
+pub fn execute_with_events(
+	parent_hash: [u8; 32],
+	parent_head: impl Into<VersionedHeadData>,
 	block_data: &BlockData,
-) -> Result<HeadData, StateMismatch> {
-	assert_eq!(parent_hash, parent_head.hash());
+) -> Result<(HeadData, Vec<Event>), ExecuteError> {
+	execute_with_hook(parent_hash, parent_head, block_data, &log_hook::DefaultLog)
+}
 
-	if hash_state(block_data.state) != parent_head.post_state {
-		return Err(StateMismatch)
+/// Same as [`execute`], but emits debug messages to the given `hook`.
+#[cfg(feature = "log-hook")]
+pub fn execute_with_log(
+	parent_hash: [u8; 32],
+	parent_head: impl Into<VersionedHeadData>,
+	block_data: &BlockData,
+	hook: &impl LogHook,
+) -> Result<HeadData, ExecuteError> {
+	execute_with_hook(parent_hash, parent_head, block_data, hook).map(|(head, _)| head)
+}
+
+fn execute_with_hook(
+	parent_hash: [u8; 32],
+	parent_head: impl Into<VersionedHeadData>,
+	block_data: &BlockData,
+	hook: &impl log_hook::LogHook,
+) -> Result<(HeadData, Vec<Event>), ExecuteError> {
+	let parent_head = check_parent(parent_hash, parent_head.into())?;
+
+	let number = parent_head.number.checked_add(1).ok_or(ExecuteError::BlockNumberOverflow)?;
+
+	if block_data.ops.len() > MAX_OPS_PER_BLOCK {
+		return Err(ExecuteError::TooManyOps)
+	}
+
+	if block_data.encoded_size() > MAX_BLOCK_SIZE {
+		return Err(ExecuteError::BlockTooLarge)
 	}
 
-	let new_state = block_data.state.wrapping_add(block_data.add);
+	if block_data.total_add() > MAX_ADD_PER_BLOCK {
+		return Err(ExecuteError::AddTooLarge)
+	}
+
+	if block_data.timestamp <= parent_head.timestamp {
+		return Err(ExecuteError::TimestampNotIncreasing)
+	}
+
+	if !parent_head.commits_to(block_data.state, block_data.mode) {
+		hook.log(format_args!(
+			"state mismatch: block starts from {} on top of block #{}",
+			block_data.state, parent_head.number,
+		));
+		return Err(ExecuteError::StateMismatch)
+	}
+
+	let new_state = block_data.apply()?;
+	hook.log(format_args!("block #{}: state {} -> {}", number, block_data.state, new_state));
+
+	let events = sp_std::vec![Event::StateChanged { from: block_data.state, to: new_state }];
+	let hash_mode = parent_head.hash_mode;
+	let history = parent_head
+		.history
+		.map(|history| fold_history(hash_mode, history, &block_data.ops));
+	let head = HeadData {
+		number,
+		parent_hash,
+		post_state: commit_state(hash_mode, history.as_ref(), new_state, block_data.mode),
+		extrinsics_root: hash_mode.hash(&block_data.encode()),
+		events_root: events_root(hash_mode, &events),
+		timestamp: block_data.timestamp,
+		hash_mode,
+		history,
+	};
 
-	Ok(HeadData { number: parent_head.number + 1, parent_hash, post_state: hash_state(new_state) })
+	Ok((head, events))
 }
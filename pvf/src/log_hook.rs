@@ -0,0 +1,68 @@
+// Copyright 2019-2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Debug logging of the block execution.
+
+use core::fmt;
+
+/// Receives the debug messages emitted while executing a block.
+pub trait LogHook {
+	/// Log a debug message.
+	fn log(&self, message: fmt::Arguments);
+}
+
+/// Discards all messages.
+pub struct NoopLog;
+
+impl LogHook for NoopLog {
+	fn log(&self, _: fmt::Arguments) {}
+}
+
+/// Forwards the messages to the logging host function of the validation host.
+#[cfg(all(feature = "log-hook", not(feature = "std")))]
+pub struct HostLog;
+
+#[cfg(all(feature = "log-hook", not(feature = "std")))]
+impl LogHook for HostLog {
+	fn log(&self, message: fmt::Arguments) {
+		use fmt::Write as _;
+
+		extern "C" {
+			fn ext_logging_log_version_1(level: u32, target: u64, message: u64);
+		}
+
+		/// Pack a slice into the pointer-size representation expected by the host.
+		fn pack(data: &[u8]) -> u64 {
+			(data.len() as u64) << 32 | data.as_ptr() as u64
+		}
+
+		/// `Debug` level of the host logging interface.
+		const DEBUG: u32 = 4;
+
+		let mut buf = alloc::string::String::new();
+		let _ = buf.write_fmt(message);
+
+		unsafe { ext_logging_log_version_1(DEBUG, pack(b"pba-pvf"), pack(buf.as_bytes())) }
+	}
+}
+
+/// The hook used by [`crate::execute`].
+#[cfg(all(feature = "log-hook", not(feature = "std")))]
+pub(crate) type DefaultLog = HostLog;
+
+/// The hook used by [`crate::execute`].
+#[cfg(not(all(feature = "log-hook", not(feature = "std"))))]
+pub(crate) type DefaultLog = NoopLog;
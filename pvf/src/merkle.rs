@@ -0,0 +1,251 @@
+// Copyright 2019-2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Alternative state model where the state is a key-value store committed to by a Merkle tree.
+//!
+//! The head commits to the root of a binary Merkle tree over the entries sorted by key, so a light
+//! client holding a head can check a single entry against it with a [`MerkleProof`].
+//!
+//! The tree, like the extrinsics and events roots, is hashed with the [`HashMode`] of the chain,
+//! chosen at genesis.
+
+use crate::{
+	check_parent, events_root, ExecuteError, HashMode, HeadData, VersionedHeadData, MAX_BLOCK_SIZE,
+};
+use parity_scale_codec::{Decode, Encode};
+use sp_std::{collections::btree_map::BTreeMap, vec::Vec};
+
+/// The maximum number of updates a single block may carry.
+pub const MAX_UPDATES_PER_BLOCK: usize = 128;
+
+/// Root of the empty state.
+pub const EMPTY_ROOT: [u8; 32] = [0; 32];
+
+/// The entries of the store. Keys without an entry have no value.
+#[derive(Default, Clone, Encode, Decode, Debug, PartialEq, Eq)]
+pub struct KvState(pub BTreeMap<Vec<u8>, Vec<u8>>);
+
+/// Hash of the leaf of an entry, domain separated from the inner nodes.
+fn leaf_hash(hash_mode: HashMode, key: &[u8], value: &[u8]) -> [u8; 32] {
+	hash_mode.hash(&(0u8, key, value).encode())
+}
+
+/// Hash of an inner node with the given children.
+fn node_hash(hash_mode: HashMode, left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+	hash_mode.hash(&(1u8, left, right).encode())
+}
+
+/// Hash the nodes of a level of the tree pairwise into the next level.
+///
+/// The last node of an odd level is carried over as is.
+fn next_level(hash_mode: HashMode, level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+	level
+		.chunks(2)
+		.map(|pair| match pair {
+			[left, right] => node_hash(hash_mode, left, right),
+			[single] => *single,
+			_ => unreachable!("chunks are made of one or two nodes"),
+		})
+		.collect()
+}
+
+impl KvState {
+	/// Get the value of `key`.
+	pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+		self.0.get(key).map(Vec::as_slice)
+	}
+
+	/// The leaves of the Merkle tree over the entries, sorted by key.
+	fn leaves(&self, hash_mode: HashMode) -> Vec<[u8; 32]> {
+		self.0.iter().map(|(key, value)| leaf_hash(hash_mode, key, value)).collect()
+	}
+
+	/// Root of the Merkle tree over the entries hashed with `hash_mode`, the commitment stored in
+	/// [`HeadData::post_state`].
+	///
+	/// [`EMPTY_ROOT`] if there are no entries.
+	pub fn root(&self, hash_mode: HashMode) -> [u8; 32] {
+		let mut level = self.leaves(hash_mode);
+		if level.is_empty() {
+			return EMPTY_ROOT
+		}
+
+		while level.len() > 1 {
+			level = next_level(hash_mode, &level);
+		}
+		level[0]
+	}
+
+	/// Prove the value of `key` against [`Self::root`] for the same `hash_mode`.
+	///
+	/// Returns `None` if `key` has no value.
+	pub fn prove(&self, hash_mode: HashMode, key: &[u8]) -> Option<MerkleProof> {
+		let leaf_index = self.0.keys().position(|k| k.as_slice() == key)?;
+		let mut level = self.leaves(hash_mode);
+		let leaf_count = level.len() as u32;
+
+		let (mut index, mut siblings) = (leaf_index, Vec::new());
+		while level.len() > 1 {
+			let sibling = index ^ 1;
+			if sibling < level.len() {
+				siblings.push(level[sibling]);
+			}
+			level = next_level(hash_mode, &level);
+			index /= 2;
+		}
+
+		Some(MerkleProof { index: leaf_index as u32, leaf_count, siblings })
+	}
+
+	/// Apply `update`, removing the entry if the new value is `None`.
+	fn apply(&mut self, update: &Update) {
+		match &update.value {
+			Some(value) => self.0.insert(update.key.clone(), value.clone()),
+			None => self.0.remove(&update.key),
+		};
+	}
+}
+
+/// Proof that an entry is part of the state with a given root, see [`KvState::prove`].
+#[derive(Clone, Encode, Decode, Debug, PartialEq, Eq)]
+pub struct MerkleProof {
+	/// Position of the entry among all entries, sorted by key.
+	pub index: u32,
+	/// Number of entries in the state.
+	pub leaf_count: u32,
+	/// Hashes of the siblings on the path from the entry up to the root.
+	pub siblings: Vec<[u8; 32]>,
+}
+
+impl MerkleProof {
+	/// Check that `key` has `value` in the state with the given `root`, hashed with `hash_mode`.
+	pub fn verify(&self, hash_mode: HashMode, root: &[u8; 32], key: &[u8], value: &[u8]) -> bool {
+		if self.index >= self.leaf_count {
+			return false
+		}
+
+		let (mut index, mut count) = (self.index, self.leaf_count);
+		let mut siblings = self.siblings.iter();
+		let mut node = leaf_hash(hash_mode, key, value);
+		while count > 1 {
+			if index % 2 == 1 {
+				match siblings.next() {
+					Some(left) => node = node_hash(hash_mode, left, &node),
+					None => return false,
+				}
+			} else if index + 1 < count {
+				match siblings.next() {
+					Some(right) => node = node_hash(hash_mode, &node, right),
+					None => return false,
+				}
+			}
+			index /= 2;
+			count = (count + 1) / 2;
+		}
+
+		siblings.next().is_none() && &node == root
+	}
+}
+
+/// Set `key` to `value`, or remove it if `value` is `None`.
+#[derive(Clone, Encode, Decode, Debug, PartialEq, Eq)]
+pub struct Update {
+	/// The key to update.
+	pub key: Vec<u8>,
+	/// The new value of the key.
+	pub value: Option<Vec<u8>>,
+}
+
+/// Block data for the key-value model.
+#[derive(Default, Clone, Encode, Decode, Debug)]
+pub struct KvBlockData {
+	/// State to begin from.
+	pub state: KvState,
+	/// Updates to apply, in order.
+	pub updates: Vec<Update>,
+}
+
+impl KvBlockData {
+	/// Apply the block's updates to `state` in order, returning the post-state.
+	pub fn apply(&self) -> KvState {
+		let mut state = self.state.clone();
+		for update in &self.updates {
+			state.apply(update);
+		}
+		state
+	}
+}
+
+/// Get the genesis head of a chain starting with the given `state`.
+pub fn genesis_head(state: &KvState) -> HeadData {
+	genesis_head_with(state, HashMode::default())
+}
+
+/// Same as [`genesis_head`], but for a chain hashing with `hash_mode`.
+pub fn genesis_head_with(state: &KvState, hash_mode: HashMode) -> HeadData {
+	HeadData {
+		number: 0,
+		parent_hash: Default::default(),
+		post_state: state.root(hash_mode),
+		extrinsics_root: Default::default(),
+		events_root: Default::default(),
+		timestamp: 0,
+		hash_mode,
+		history: None,
+	}
+}
+
+/// Execute a block of updates on top of given parent head, producing new parent head
+/// if valid.
+///
+/// See [`crate::execute`] for the versions the parent head may be of.
+pub fn execute(
+	parent_hash: [u8; 32],
+	parent_head: impl Into<VersionedHeadData>,
+	block_data: &KvBlockData,
+) -> Result<HeadData, ExecuteError> {
+	let parent_head = check_parent(parent_hash, parent_head.into())?;
+
+	let number = parent_head.number.checked_add(1).ok_or(ExecuteError::BlockNumberOverflow)?;
+
+	if block_data.updates.len() > MAX_UPDATES_PER_BLOCK {
+		return Err(ExecuteError::TooManyUpdates)
+	}
+
+	if block_data.encoded_size() > MAX_BLOCK_SIZE {
+		return Err(ExecuteError::BlockTooLarge)
+	}
+
+	let hash_mode = parent_head.hash_mode;
+	if block_data.state.root(hash_mode) != parent_head.post_state {
+		return Err(ExecuteError::StateMismatch)
+	}
+
+	let new_state = block_data.apply();
+
+	Ok(HeadData {
+		number,
+		parent_hash,
+		post_state: new_state.root(hash_mode),
+		extrinsics_root: hash_mode.hash(&block_data.encode()),
+		events_root: events_root(hash_mode, &[]),
+		// Update blocks don't carry a timestamp.
+		timestamp: parent_head.timestamp,
+		hash_mode,
+		// Updates are not folded into a history.
+		history: None,
+	})
+}
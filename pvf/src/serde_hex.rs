@@ -0,0 +1,56 @@
+// Copyright 2019-2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! (De)serialize hashes as `0x` prefixed hex strings.
+
+use alloc::{format, string::String};
+use serde::{de::Error as _, Deserialize, Deserializer, Serializer};
+
+pub fn serialize<S: Serializer>(bytes: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error> {
+	serializer.serialize_str(&format!("0x{}", hex::encode(bytes)))
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 32], D::Error> {
+	let hex = String::deserialize(deserializer)?;
+	let mut bytes = [0u8; 32];
+	hex::decode_to_slice(hex.strip_prefix("0x").unwrap_or(&hex), &mut bytes)
+		.map_err(D::Error::custom)?;
+	Ok(bytes)
+}
+
+/// (De)serialize optional hashes as `0x` prefixed hex strings or `null`.
+pub mod option {
+	use serde::{Deserialize, Deserializer, Serializer};
+
+	pub fn serialize<S: Serializer>(
+		bytes: &Option<[u8; 32]>,
+		serializer: S,
+	) -> Result<S::Ok, S::Error> {
+		match bytes {
+			Some(bytes) => super::serialize(bytes, serializer),
+			None => serializer.serialize_none(),
+		}
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(
+		deserializer: D,
+	) -> Result<Option<[u8; 32]>, D::Error> {
+		#[derive(Deserialize)]
+		struct Hex(#[serde(with = "super")] [u8; 32]);
+
+		Ok(Option::<Hex>::deserialize(deserializer)?.map(|Hex(bytes)| bytes))
+	}
+}
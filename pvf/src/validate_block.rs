@@ -16,30 +16,106 @@
 
 //! WASM validation for PBA parachain.
 
-use crate::{hash, BlockData, HeadData};
+use crate::{ExecuteError, HeadData, VersionedHeadData};
 use core::panic;
 use parity_scale_codec::{Decode, Encode};
-use polkadot_parachain::primitives::{HeadData as GenericHeadData, ValidationResult};
+use polkadot_core_primitives::OutboundHrmpMessage;
+use polkadot_parachain::primitives::{
+	HeadData as GenericHeadData, Id, ValidationCode, ValidationResult,
+};
 use sp_std::vec::Vec;
 
-#[no_mangle]
-pub extern "C" fn validate_block(params: *const u8, len: usize) -> u64 {
+/// What a block hands to the relay chain besides its head.
+#[derive(Default)]
+struct Outputs {
+	/// The code to upgrade to, if any.
+	new_validation_code: Option<ValidationCode>,
+	/// Messages sent to the relay chain.
+	upward_messages: Vec<Vec<u8>>,
+	/// Messages sent to other parachains.
+	horizontal_messages: Vec<OutboundHrmpMessage<Id>>,
+	/// Number of downward messages processed.
+	processed_downward_messages: u32,
+}
+
+/// Validate the block of type `B` in the parameters at `params`, executing it with `execute` and
+/// taking what it hands to the relay chain from `outputs`.
+///
+/// Panics if the block is invalid, as validators expect.
+fn validate<B: Decode>(
+	params: *const u8,
+	len: usize,
+	execute: impl FnOnce([u8; 32], VersionedHeadData, &B) -> Result<HeadData, ExecuteError>,
+	outputs: impl FnOnce(B) -> Outputs,
+) -> u64 {
 	let params = unsafe { polkadot_parachain::load_params(params, len) };
-	let parent_head =
-		HeadData::decode(&mut &params.parent_head.0[..]).expect("invalid parent head format.");
+	let parent_head = VersionedHeadData::decode(&mut &params.parent_head.0[..])
+		.expect("invalid parent head format.");
 
-	let block_data =
-		BlockData::decode(&mut &params.block_data.0[..]).expect("invalid block data format.");
+	let block_data = B::decode(&mut &params.block_data.0[..]).expect("invalid block data format.");
 
-	let parent_hash = hash(&params.parent_head.0[..]);
+	// The hash of the head as stored by the relay chain, rather than of the migrated head.
+	let parent_hash = parent_head.hash_mode().hash(&params.parent_head.0);
 
-	let new_head = crate::execute(parent_hash, parent_head, &block_data).expect("Executes block");
+	let new_head = execute(parent_hash, parent_head, &block_data).expect("Executes block");
+	let outputs = outputs(block_data);
 	polkadot_parachain::write_result(&ValidationResult {
 		head_data: GenericHeadData(new_head.encode()),
-		new_validation_code: None,
-		upward_messages: sp_std::vec::Vec::new(),
-		horizontal_messages: sp_std::vec::Vec::new(),
-		processed_downward_messages: 0,
+		new_validation_code: outputs.new_validation_code,
+		upward_messages: outputs.upward_messages,
+		horizontal_messages: outputs.horizontal_messages,
+		processed_downward_messages: outputs.processed_downward_messages,
 		hrmp_watermark: params.relay_parent_number,
 	})
 }
+
+#[cfg(not(any(feature = "balances", feature = "merkle")))]
+#[no_mangle]
+pub extern "C" fn validate_block(params: *const u8, len: usize) -> u64 {
+	validate(
+		params,
+		len,
+		|parent_hash, parent_head, block_data: &crate::BlockData| {
+			crate::execute(parent_hash, parent_head, block_data)
+		},
+		|block_data| Outputs {
+			new_validation_code: block_data.new_validation_code.map(ValidationCode),
+			upward_messages: block_data.upward_messages,
+			horizontal_messages: block_data
+				.horizontal_messages
+				.into_iter()
+				.map(|msg| OutboundHrmpMessage {
+					recipient: Id::from(msg.recipient),
+					data: msg.data,
+				})
+				.collect(),
+			processed_downward_messages: block_data.processed_downward_messages,
+		},
+	)
+}
+
+#[cfg(feature = "balances")]
+#[no_mangle]
+pub extern "C" fn validate_block(params: *const u8, len: usize) -> u64 {
+	validate(
+		params,
+		len,
+		|parent_hash, parent_head, block_data: &crate::balances::TransferBlockData| {
+			crate::balances::execute(parent_hash, parent_head, block_data)
+		},
+		|_| Outputs::default(),
+	)
+}
+
+#[cfg(all(feature = "merkle", not(feature = "balances")))]
+#[no_mangle]
+pub extern "C" fn validate_block(params: *const u8, len: usize) -> u64 {
+	validate(
+		params,
+		len,
+		|parent_hash, parent_head, block_data: &crate::merkle::KvBlockData| {
+			crate::merkle::execute(parent_hash, parent_head, block_data)
+		},
+		|_| Outputs::default(),
+	)
+}
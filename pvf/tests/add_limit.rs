@@ -0,0 +1,49 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Checks the limit on the amount a block may add.
+
+use pba_pvf::{
+	execute, genesis_head, ArithmeticMode, BlockData, ExecuteError, Op, MAX_ADD_PER_BLOCK,
+};
+
+fn block_adding(ops: Vec<Op>) -> BlockData {
+	BlockData { state: 0, ops, timestamp: 1, ..Default::default() }
+}
+
+#[test]
+fn add_at_the_limit_is_accepted() {
+	let genesis = genesis_head(0, ArithmeticMode::Wrapping);
+	let block = block_adding(vec![Op::Add(MAX_ADD_PER_BLOCK)]);
+
+	assert!(execute(genesis.hash(), genesis, &block).is_ok());
+}
+
+#[test]
+fn add_over_the_limit_is_rejected() {
+	let genesis = genesis_head(0, ArithmeticMode::Wrapping);
+	let block = block_adding(vec![Op::Add(MAX_ADD_PER_BLOCK + 1)]);
+
+	assert_eq!(execute(genesis.hash(), genesis, &block), Err(ExecuteError::AddTooLarge));
+}
+
+#[test]
+fn limit_applies_to_all_operations_of_a_block() {
+	let genesis = genesis_head(0, ArithmeticMode::Wrapping);
+	let block = block_adding(vec![Op::Add(MAX_ADD_PER_BLOCK), Op::Sub(1), Op::Add(1)]);
+
+	assert_eq!(execute(genesis.hash(), genesis, &block), Err(ExecuteError::AddTooLarge));
+}
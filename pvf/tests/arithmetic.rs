@@ -0,0 +1,69 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Checks the arithmetic modes at the boundaries of `u64`.
+
+use pba_pvf::{execute, genesis_head, ArithmeticMode, BlockData, ExecuteError, Op};
+
+/// Apply `op` to `state` in `mode`, both natively and through [`execute`], returning the
+/// post-state.
+fn apply(mode: ArithmeticMode, state: u64, op: Op) -> Result<u64, ExecuteError> {
+	let genesis = genesis_head(state, mode);
+	let block = BlockData { state, ops: vec![op], timestamp: 1, mode, ..Default::default() };
+
+	let result = block.apply();
+	match execute(genesis.hash(), genesis, &block) {
+		Ok(head) => assert!(head.commits_to(*result.as_ref().unwrap(), mode)),
+		Err(e) => assert_eq!(result.as_ref(), Err(&e)),
+	}
+	result
+}
+
+#[test]
+fn wrapping_wraps_around() {
+	let mode = ArithmeticMode::Wrapping;
+
+	assert_eq!(apply(mode, u64::MAX, Op::Add(1)), Ok(0));
+	assert_eq!(apply(mode, 0, Op::Sub(1)), Ok(u64::MAX));
+	assert_eq!(apply(mode, u64::MAX, Op::Mul(2)), Ok(u64::MAX - 1));
+}
+
+#[test]
+fn checked_rejects_overflows() {
+	let mode = ArithmeticMode::Checked;
+
+	assert_eq!(apply(mode, u64::MAX, Op::Add(1)), Err(ExecuteError::Overflow));
+	assert_eq!(apply(mode, 0, Op::Sub(1)), Err(ExecuteError::Overflow));
+	assert_eq!(apply(mode, u64::MAX, Op::Mul(2)), Err(ExecuteError::Overflow));
+}
+
+#[test]
+fn checked_accepts_results_at_the_boundaries() {
+	let mode = ArithmeticMode::Checked;
+
+	assert_eq!(apply(mode, u64::MAX - 1, Op::Add(1)), Ok(u64::MAX));
+	assert_eq!(apply(mode, 1, Op::Sub(1)), Ok(0));
+	assert_eq!(apply(mode, u64::MAX, Op::Mul(1)), Ok(u64::MAX));
+}
+
+#[test]
+fn saturating_clamps_at_the_boundaries() {
+	let mode = ArithmeticMode::Saturating;
+
+	assert_eq!(apply(mode, u64::MAX, Op::Add(1)), Ok(u64::MAX));
+	assert_eq!(apply(mode, 0, Op::Sub(1)), Ok(0));
+	assert_eq!(apply(mode, u64::MAX, Op::Mul(2)), Ok(u64::MAX));
+}
@@ -0,0 +1,212 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Checks the signed transfers of the balances model.
+
+#![cfg(feature = "balances")]
+
+use pba_pvf::{
+	balances::{
+		execute, genesis_head, genesis_head_with, Account, Balances, SignedTransfer, Transfer,
+		TransferBlockData, MAX_TRANSFERS_PER_BLOCK,
+	},
+	ExecuteError, HashMode, HeadData,
+};
+use sp_core::{sr25519, Pair};
+
+fn alice() -> sr25519::Pair {
+	sr25519::Pair::from_seed(&[1; 32])
+}
+
+fn bob() -> sr25519::Pair {
+	sr25519::Pair::from_seed(&[2; 32])
+}
+
+fn genesis_balances() -> Balances {
+	let alice_account = Account { balance: 100, nonce: 0 };
+	Balances(std::iter::once((alice().public().0, alice_account)).collect())
+}
+
+/// A transfer of `amount` from alice to bob, the `nonce`th one alice sends.
+fn transfer(amount: u64, nonce: u64) -> Transfer {
+	Transfer { from: alice().public().0, to: bob().public().0, amount, nonce }
+}
+
+/// Execute a block with the given `transfers` on top of `parent`, whose balances are `balances`.
+fn execute_transfers(
+	parent: HeadData,
+	balances: Balances,
+	transfers: Vec<SignedTransfer>,
+) -> Result<HeadData, ExecuteError> {
+	execute(parent.hash(), parent, &TransferBlockData { balances, transfers })
+}
+
+#[test]
+fn validly_signed_transfer_is_applied() {
+	let genesis = genesis_head(&genesis_balances());
+	let block = TransferBlockData {
+		balances: genesis_balances(),
+		transfers: vec![SignedTransfer::sign(transfer(30, 0), 1, &alice())],
+	};
+
+	let head = execute(genesis.hash(), genesis, &block).unwrap();
+	let balances = block.apply().unwrap();
+	assert_eq!(head.post_state, balances.hash(HashMode::default()));
+	assert_eq!(balances.balance(&alice().public().0), 70);
+	assert_eq!(balances.balance(&bob().public().0), 30);
+	assert_eq!(balances.nonce(&alice().public().0), 1);
+	assert_eq!(balances.nonce(&bob().public().0), 0);
+}
+
+#[test]
+fn transfers_with_increasing_nonces_are_applied() {
+	let block = TransferBlockData {
+		balances: genesis_balances(),
+		transfers: vec![
+			SignedTransfer::sign(transfer(30, 0), 1, &alice()),
+			SignedTransfer::sign(transfer(30, 1), 1, &alice()),
+		],
+	};
+
+	let balances = block.apply().unwrap();
+	assert_eq!(balances.balance(&alice().public().0), 40);
+	assert_eq!(balances.nonce(&alice().public().0), 2);
+}
+
+#[test]
+fn repeated_transfer_is_rejected() {
+	let genesis = genesis_head(&genesis_balances());
+	let signed = SignedTransfer::sign(transfer(30, 0), 1, &alice());
+
+	assert_eq!(
+		execute_transfers(genesis, genesis_balances(), vec![signed.clone(), signed]),
+		Err(ExecuteError::InvalidNonce),
+	);
+}
+
+#[test]
+fn nonce_is_kept_once_the_balance_is_spent() {
+	let block = TransferBlockData {
+		balances: genesis_balances(),
+		transfers: vec![SignedTransfer::sign(transfer(100, 0), 1, &alice())],
+	};
+
+	let balances = block.apply().unwrap();
+	assert_eq!(balances.balance(&alice().public().0), 0);
+	assert_eq!(balances.nonce(&alice().public().0), 1);
+}
+
+#[test]
+fn transfer_with_a_future_nonce_is_rejected() {
+	let genesis = genesis_head(&genesis_balances());
+	let signed = SignedTransfer::sign(transfer(30, 1), 1, &alice());
+
+	assert_eq!(
+		execute_transfers(genesis, genesis_balances(), vec![signed]),
+		Err(ExecuteError::InvalidNonce),
+	);
+}
+
+#[test]
+fn credit_overflowing_a_balance_is_rejected() {
+	let mut balances = genesis_balances();
+	balances.0.insert(bob().public().0, Account { balance: u64::MAX, nonce: 0 });
+	let genesis = genesis_head(&balances);
+	let signed = SignedTransfer::sign(transfer(30, 0), 1, &alice());
+
+	assert_eq!(
+		execute_transfers(genesis, balances, vec![signed]),
+		Err(ExecuteError::BalanceOverflow),
+	);
+}
+
+#[test]
+fn forged_signature_is_rejected() {
+	let genesis = genesis_head(&genesis_balances());
+	// Bob signs a transfer out of alice's account.
+	let forged = SignedTransfer::sign(transfer(30, 0), 1, &bob());
+
+	assert_eq!(
+		execute_transfers(genesis, genesis_balances(), vec![forged]),
+		Err(ExecuteError::InvalidSignature),
+	);
+}
+
+#[test]
+fn tampered_transfer_is_rejected() {
+	let genesis = genesis_head(&genesis_balances());
+	let mut tampered = SignedTransfer::sign(transfer(30, 0), 1, &alice());
+	tampered.transfer.amount = 100;
+
+	assert_eq!(
+		execute_transfers(genesis, genesis_balances(), vec![tampered]),
+		Err(ExecuteError::InvalidSignature),
+	);
+}
+
+#[test]
+fn transfer_can_not_be_replayed_in_a_later_block() {
+	let genesis = genesis_head(&genesis_balances());
+	let signed = SignedTransfer::sign(transfer(30, 0), 1, &alice());
+	let block = TransferBlockData { balances: genesis_balances(), transfers: vec![signed.clone()] };
+	let head = execute(genesis.hash(), genesis, &block).unwrap();
+
+	assert_eq!(
+		execute_transfers(head, block.apply().unwrap(), vec![signed]),
+		Err(ExecuteError::InvalidSignature),
+	);
+}
+
+#[test]
+fn too_many_transfers_are_rejected() {
+	let genesis = genesis_head(&genesis_balances());
+	let transfers = (0..=MAX_TRANSFERS_PER_BLOCK)
+		.map(|_| SignedTransfer::sign(transfer(0, 0), 1, &alice()))
+		.collect();
+
+	assert_eq!(
+		execute_transfers(genesis, genesis_balances(), transfers),
+		Err(ExecuteError::TooManyTransfers),
+	);
+}
+
+#[test]
+fn balances_are_hashed_with_the_hash_mode_of_the_chain() {
+	let genesis = genesis_head_with(&genesis_balances(), HashMode::Sha256);
+	assert_eq!(genesis.post_state, genesis_balances().hash(HashMode::Sha256));
+
+	let block = TransferBlockData {
+		balances: genesis_balances(),
+		transfers: vec![SignedTransfer::sign(transfer(30, 0), 1, &alice())],
+	};
+	let head = execute(genesis.hash(), genesis, &block).unwrap();
+	let balances = block.apply().unwrap();
+	assert_eq!(head.hash_mode, HashMode::Sha256);
+	assert_eq!(head.post_state, balances.hash(HashMode::Sha256));
+	assert_ne!(head.post_state, balances.hash(HashMode::Blake3));
+}
+
+#[test]
+fn balances_hashed_with_another_hash_mode_are_rejected() {
+	let genesis = genesis_head(&genesis_balances());
+	let mut parent = genesis.clone();
+	parent.post_state = genesis_balances().hash(HashMode::Keccak256);
+
+	assert_eq!(
+		execute_transfers(parent, genesis_balances(), vec![]),
+		Err(ExecuteError::StateMismatch),
+	);
+}
@@ -0,0 +1,42 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Checks that the block number can't advance past `u64::MAX`.
+
+use pba_pvf::{execute, genesis_head, ArithmeticMode, BlockData, ExecuteError, HeadData};
+
+fn head_at(number: u64) -> HeadData {
+	HeadData { number, ..genesis_head(0, ArithmeticMode::Wrapping) }
+}
+
+fn block() -> BlockData {
+	BlockData { state: 0, timestamp: 1, ..Default::default() }
+}
+
+#[test]
+fn block_before_the_maximum_number_is_accepted() {
+	let parent = head_at(u64::MAX - 1);
+
+	let head = execute(parent.hash(), parent, &block()).unwrap();
+	assert_eq!(head.number, u64::MAX);
+}
+
+#[test]
+fn block_on_top_of_the_maximum_number_is_rejected() {
+	let parent = head_at(u64::MAX);
+
+	assert_eq!(execute(parent.hash(), parent, &block()), Err(ExecuteError::BlockNumberOverflow));
+}
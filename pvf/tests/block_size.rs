@@ -0,0 +1,47 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Checks the block size limit of [`execute`].
+
+use parity_scale_codec::Encode;
+use pba_pvf::{execute, genesis_head, ArithmeticMode, BlockData, ExecuteError, MAX_BLOCK_SIZE};
+
+/// A block on top of genesis whose encoding is exactly `size` bytes.
+fn block_of_size(size: usize) -> BlockData {
+	let mut block =
+		BlockData { upward_messages: vec![Vec::new()], timestamp: 1, ..Default::default() };
+	// The length prefix of the message grows from one to four bytes.
+	let message_len = size - block.encoded_size() - 3;
+	block.upward_messages[0] = vec![0; message_len];
+	assert_eq!(block.encoded_size(), size);
+	block
+}
+
+#[test]
+fn block_at_the_limit_is_accepted() {
+	let genesis = genesis_head(0, ArithmeticMode::Wrapping);
+	let block = block_of_size(MAX_BLOCK_SIZE);
+
+	assert!(execute(genesis.hash(), genesis, &block).is_ok());
+}
+
+#[test]
+fn block_over_the_limit_is_rejected() {
+	let genesis = genesis_head(0, ArithmeticMode::Wrapping);
+	let block = block_of_size(MAX_BLOCK_SIZE + 1);
+
+	assert_eq!(execute(genesis.hash(), genesis, &block), Err(ExecuteError::BlockTooLarge));
+}
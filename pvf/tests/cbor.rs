@@ -0,0 +1,38 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! CBOR interchange format of the head data.
+
+#![cfg(feature = "cbor")]
+
+use pba_pvf::{genesis_head_with, ArithmeticMode, CborError, HashMode, HeadData, StateCommitment};
+
+fn head() -> HeadData {
+	genesis_head_with(7, ArithmeticMode::Checked, HashMode::Keccak256, StateCommitment::Rolling)
+}
+
+#[test]
+fn head_round_trips_through_cbor() {
+	assert_eq!(HeadData::from_cbor(&head().to_cbor()).unwrap(), head());
+}
+
+#[test]
+fn trailing_bytes_are_rejected() {
+	let mut cbor = head().to_cbor();
+	cbor.push(0);
+
+	assert!(matches!(HeadData::from_cbor(&cbor), Err(CborError::Malformed(_))));
+}
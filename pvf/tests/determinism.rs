@@ -0,0 +1,92 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Checks that executing a block only depends on its inputs.
+
+use parity_scale_codec::Encode;
+use pba_pvf::{
+	execute, genesis_head, ArithmeticMode, BlockData, ExecuteError, HeadData, Op,
+	MAX_ADD_PER_BLOCK, MAX_OPS_PER_BLOCK,
+};
+use proptest::prelude::*;
+
+/// An operation that keeps every block within [`MAX_ADD_PER_BLOCK`].
+fn op() -> impl Strategy<Value = Op> {
+	prop_oneof![
+		(0..=MAX_ADD_PER_BLOCK / MAX_OPS_PER_BLOCK as u64).prop_map(Op::Add),
+		any::<u64>().prop_map(Op::Sub),
+		any::<u64>().prop_map(Op::Mul),
+	]
+}
+
+/// The operations of up to 16 blocks.
+fn chain() -> impl Strategy<Value = Vec<Vec<Op>>> {
+	prop::collection::vec(prop::collection::vec(op(), 0..=MAX_OPS_PER_BLOCK), 1..16)
+}
+
+/// Build a valid chain on top of a genesis with `genesis_value`, returning the inputs of every
+/// block together with the head it produced.
+fn build_chain(genesis_value: u64, chain: Vec<Vec<Op>>) -> Vec<(HeadData, BlockData, HeadData)> {
+	let mut parent = genesis_head(genesis_value, ArithmeticMode::Wrapping);
+	let mut state = genesis_value;
+
+	chain
+		.into_iter()
+		.map(|ops| {
+			let block =
+				BlockData { state, ops, timestamp: parent.timestamp + 1, ..Default::default() };
+			state = block.apply().expect("Wrapping blocks don't fail");
+
+			let head = execute(parent.hash(), parent.clone(), &block).expect("Block is valid");
+			let parent = std::mem::replace(&mut parent, head.clone());
+			(parent, block, head)
+		})
+		.collect()
+}
+
+/// The check is an error rather than an assertion, so this passes with and without `--release`.
+#[test]
+fn wrong_parent_hash_is_an_error_in_every_profile() {
+	let genesis = genesis_head(0, ArithmeticMode::Wrapping);
+	let block = BlockData { timestamp: 1, ..Default::default() };
+
+	assert_eq!(execute([0; 32], genesis, &block), Err(ExecuteError::ParentHashMismatch));
+}
+
+proptest! {
+	#[test]
+	fn re_execution_yields_identical_heads(genesis_value in any::<u64>(), chain in chain()) {
+		for (parent, block, head) in build_chain(genesis_value, chain) {
+			let replayed = execute(parent.hash(), parent, &block).expect("Block is valid");
+			prop_assert_eq!(replayed.encode(), head.encode());
+		}
+	}
+
+	#[test]
+	fn tampered_parent_hash_is_rejected(
+		genesis_value in any::<u64>(),
+		chain in chain(),
+		tampered_hash in any::<[u8; 32]>(),
+	) {
+		for (parent, block, _) in build_chain(genesis_value, chain) {
+			prop_assume!(tampered_hash != parent.hash());
+			prop_assert_eq!(
+				execute(tampered_hash, parent, &block),
+				Err(ExecuteError::ParentHashMismatch),
+			);
+		}
+	}
+}
@@ -0,0 +1,114 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Known answers of the hash modes, which external verifiers must be able to reproduce.
+
+use parity_scale_codec::Encode;
+use pba_pvf::{
+	execute, genesis_head_with, hash_state_with, ArithmeticMode, BlockData, HashMode, Hasher,
+	Keccak256, Op, Sha256, StateCommitment,
+};
+
+fn from_hex(hex: &str) -> [u8; 32] {
+	let mut bytes = [0u8; 32];
+	for (i, byte) in bytes.iter_mut().enumerate() {
+		*byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap();
+	}
+	bytes
+}
+
+#[test]
+fn keccak_of_empty_input() {
+	assert_eq!(
+		Keccak256::hash(&[]),
+		from_hex("c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"),
+	);
+}
+
+#[test]
+fn keccak_genesis_head() {
+	let genesis =
+		genesis_head_with(0, ArithmeticMode::Wrapping, HashMode::Keccak256, StateCommitment::Value);
+
+	assert_eq!(
+		genesis.post_state,
+		from_hex("ad315e209dd62516ab8c7d1c2d8c3c206525501ebef91d12c34431f9ea255371"),
+	);
+	assert_eq!(
+		genesis.hash(),
+		from_hex("52ff88fe5473f977cfcc5fbd3e20ccb7b45b43ccf80439a8aeac1bac639cffaa"),
+	);
+	assert_eq!(genesis.hash(), Keccak256::hash(&genesis.encode()));
+}
+
+#[test]
+fn keccak_mode_is_inherited() {
+	let genesis =
+		genesis_head_with(0, ArithmeticMode::Wrapping, HashMode::Keccak256, StateCommitment::Value);
+	let block = BlockData { state: 0, ops: vec![Op::Add(7)], timestamp: 1, ..Default::default() };
+
+	let head = execute(genesis.hash(), genesis, &block).unwrap();
+	assert_eq!(head.hash_mode, HashMode::Keccak256);
+	assert_eq!(head.post_state, HashMode::Keccak256.hash_state(7, ArithmeticMode::Wrapping));
+	assert_eq!(
+		head.extrinsics_root,
+		from_hex("b0ea9499ab4e7be120104bd6c341c258b77a4478a3bbae6c63b975584965c939"),
+	);
+	assert_eq!(head.extrinsics_root, Keccak256::hash(&block.encode()));
+	// A single `Event::StateChanged { from: 0, to: 7 }`.
+	assert_eq!(
+		head.events_root,
+		from_hex("44f09f743e2b13bad89d3bc39652f9842f4139fa74e594ff9bf8af3fc472ce5b"),
+	);
+}
+
+#[test]
+fn sha256_genesis_state() {
+	assert_eq!(
+		hash_state_with::<Sha256>(0, ArithmeticMode::Wrapping),
+		from_hex("3e7077fd2f66d689e0cee6a7cf5b37bf2dca7c979af356d0a31cbc5c85605c7d"),
+	);
+}
+
+#[test]
+fn sha256_genesis_head() {
+	let genesis =
+		genesis_head_with(0, ArithmeticMode::Wrapping, HashMode::Sha256, StateCommitment::Value);
+
+	assert_eq!(
+		genesis.hash(),
+		from_hex("a30d31ebd7a20d55a6f21012d12eb5650e624d87f6c001e58286b382f0a8a567"),
+	);
+}
+
+#[test]
+fn sha256_block_commitments() {
+	let genesis =
+		genesis_head_with(0, ArithmeticMode::Wrapping, HashMode::Sha256, StateCommitment::Value);
+	let block = BlockData { state: 0, ops: vec![Op::Add(7)], timestamp: 1, ..Default::default() };
+
+	let head = execute(genesis.hash(), genesis, &block).unwrap();
+	assert_eq!(
+		head.extrinsics_root,
+		from_hex("0a8c2033c0756718de119f0c6c03cc645554ad20d8f7fa3401e7d6122f47e383"),
+	);
+	assert_eq!(head.extrinsics_root, Sha256::hash(&block.encode()));
+	// A single `Event::StateChanged { from: 0, to: 7 }`.
+	assert_eq!(
+		head.events_root,
+		from_hex("5dcd4056be02bc1ce85f2bf77c6f14ddf96ac5db7ca332b82263a0cfe609b334"),
+	);
+}
@@ -0,0 +1,184 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Checks the Merkle proofs and the execution of the key-value model.
+
+#![cfg(feature = "merkle")]
+
+use pba_pvf::{
+	merkle::{
+		execute, genesis_head, genesis_head_with, KvBlockData, KvState, Update, EMPTY_ROOT,
+		MAX_UPDATES_PER_BLOCK,
+	},
+	ExecuteError, HashMode,
+};
+
+const MODE: HashMode = HashMode::Blake3;
+
+/// A state with the `n` entries `[i] => [i, i]`.
+fn state(n: u8) -> KvState {
+	KvState((0..n).map(|i| (vec![i], vec![i, i])).collect())
+}
+
+fn set(key: u8, value: u8) -> Update {
+	Update { key: vec![key], value: Some(vec![value]) }
+}
+
+#[test]
+fn every_entry_is_proven_against_the_root() {
+	for mode in [HashMode::Blake3, HashMode::Keccak256, HashMode::Sha256] {
+		// Odd sizes make the last node of a level carry over to the next one.
+		for n in 1..=7 {
+			let state = state(n);
+			let root = state.root(mode);
+			for i in 0..n {
+				let proof = state.prove(mode, &[i]).unwrap();
+				assert!(proof.verify(mode, &root, &[i], &[i, i]), "{:?}: {} of {}", mode, i, n);
+			}
+		}
+	}
+}
+
+#[test]
+fn last_entry_of_an_odd_state_skips_a_level() {
+	let state = state(3);
+	let proof = state.prove(MODE, &[2]).unwrap();
+
+	// The third leaf has no sibling, only the hash of the first two leaves one level up.
+	assert_eq!(proof.siblings.len(), 1);
+	assert!(proof.verify(MODE, &state.root(MODE), &[2], &[2, 2]));
+}
+
+#[test]
+fn empty_state_has_the_empty_root_and_no_proofs() {
+	assert_eq!(KvState::default().root(MODE), EMPTY_ROOT);
+	assert_eq!(KvState::default().prove(MODE, &[0]), None);
+}
+
+#[test]
+fn missing_key_has_no_proof() {
+	assert_eq!(state(3).prove(MODE, &[3]), None);
+}
+
+#[test]
+fn tampered_value_fails_to_verify() {
+	let state = state(5);
+	let proof = state.prove(MODE, &[1]).unwrap();
+
+	assert!(!proof.verify(MODE, &state.root(MODE), &[1], &[1, 2]));
+	assert!(!proof.verify(MODE, &state.root(MODE), &[2], &[1, 1]));
+}
+
+#[test]
+fn tampered_proof_fails_to_verify() {
+	let state = state(5);
+	let root = state.root(MODE);
+	let proof = state.prove(MODE, &[1]).unwrap();
+
+	let mut tampered = proof.clone();
+	tampered.siblings[0][0] ^= 1;
+	assert!(!tampered.verify(MODE, &root, &[1], &[1, 1]));
+
+	let mut tampered = proof.clone();
+	tampered.index = 0;
+	assert!(!tampered.verify(MODE, &root, &[1], &[1, 1]));
+
+	let mut tampered = proof.clone();
+	tampered.index = tampered.leaf_count;
+	assert!(!tampered.verify(MODE, &root, &[1], &[1, 1]));
+
+	let mut tampered = proof.clone();
+	tampered.siblings.pop();
+	assert!(!tampered.verify(MODE, &root, &[1], &[1, 1]));
+
+	let mut tampered = proof;
+	tampered.siblings.push(root);
+	assert!(!tampered.verify(MODE, &root, &[1], &[1, 1]));
+}
+
+#[test]
+fn proof_fails_for_another_hash_mode() {
+	let state = state(5);
+	let proof = state.prove(MODE, &[1]).unwrap();
+
+	assert!(!proof.verify(HashMode::Sha256, &state.root(MODE), &[1], &[1, 1]));
+	assert!(!proof.verify(HashMode::Sha256, &state.root(HashMode::Sha256), &[1], &[1, 1]));
+}
+
+#[test]
+fn proof_fails_against_another_root() {
+	let proof = state(5).prove(MODE, &[1]).unwrap();
+	assert!(!proof.verify(MODE, &state(4).root(MODE), &[1], &[1, 1]));
+}
+
+#[test]
+fn updates_are_committed_to_by_the_new_head() {
+	let genesis = genesis_head(&state(3));
+	let block = KvBlockData {
+		state: state(3),
+		updates: vec![set(0, 7), set(5, 9), Update { key: vec![1], value: None }],
+	};
+
+	let head = execute(genesis.hash(), genesis.clone(), &block).unwrap();
+	let new_state = block.apply();
+	assert_eq!(head.number, 1);
+	assert_eq!(head.parent_hash, genesis.hash());
+	assert_eq!(head.post_state, new_state.root(MODE));
+
+	assert_eq!(new_state.get(&[0]), Some(&[7][..]));
+	assert_eq!(new_state.get(&[1]), None);
+	assert_eq!(new_state.get(&[5]), Some(&[9][..]));
+	let proof = new_state.prove(MODE, &[5]).unwrap();
+	assert!(proof.verify(MODE, &head.post_state, &[5], &[9]));
+}
+
+#[test]
+fn block_not_starting_from_the_parent_state_is_rejected() {
+	let genesis = genesis_head(&state(3));
+	let block = KvBlockData { state: state(2), updates: vec![set(0, 7)] };
+
+	assert_eq!(execute(genesis.hash(), genesis, &block), Err(ExecuteError::StateMismatch));
+}
+
+#[test]
+fn wrong_parent_hash_is_rejected() {
+	let genesis = genesis_head(&state(3));
+	let block = KvBlockData { state: state(3), updates: vec![set(0, 7)] };
+
+	assert_eq!(execute([0; 32], genesis, &block), Err(ExecuteError::ParentHashMismatch));
+}
+
+#[test]
+fn too_many_updates_are_rejected() {
+	let genesis = genesis_head(&state(3));
+	let block =
+		KvBlockData { state: state(3), updates: vec![set(0, 7); MAX_UPDATES_PER_BLOCK + 1] };
+
+	assert_eq!(execute(genesis.hash(), genesis, &block), Err(ExecuteError::TooManyUpdates));
+}
+
+#[test]
+fn tree_is_hashed_with_the_hash_mode_of_the_chain() {
+	let genesis = genesis_head_with(&state(3), HashMode::Keccak256);
+	assert_eq!(genesis.post_state, state(3).root(HashMode::Keccak256));
+
+	let block = KvBlockData { state: state(3), updates: vec![set(5, 9)] };
+	let head = execute(genesis.hash(), genesis, &block).unwrap();
+	let new_state = block.apply();
+	assert_eq!(head.hash_mode, HashMode::Keccak256);
+	assert_eq!(head.post_state, new_state.root(HashMode::Keccak256));
+	assert_ne!(head.post_state, new_state.root(HashMode::Blake3));
+}
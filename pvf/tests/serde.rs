@@ -0,0 +1,75 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! JSON round trips of the block and head data, see the `serde` feature.
+
+#![cfg(feature = "serde")]
+
+use parity_scale_codec::Encode;
+use pba_pvf::{
+	execute, genesis_head_with, ArithmeticMode, BlockData, HashMode, HeadData, HorizontalMessage,
+	Op, StateCommitment,
+};
+
+fn block() -> BlockData {
+	BlockData {
+		state: 0,
+		ops: vec![Op::Add(7), Op::Mul(3), Op::Sub(1)],
+		upward_messages: vec![vec![1, 2, 3]],
+		horizontal_messages: vec![HorizontalMessage { recipient: 2000, data: vec![4, 5] }],
+		processed_downward_messages: 1,
+		new_validation_code: Some(vec![0, 97, 115, 109]),
+		timestamp: 1,
+		mode: ArithmeticMode::Wrapping,
+	}
+}
+
+#[test]
+fn head_round_trips_through_json() {
+	let genesis = genesis_head_with(
+		0,
+		ArithmeticMode::Wrapping,
+		HashMode::Keccak256,
+		StateCommitment::Rolling,
+	);
+	let head = execute(genesis.hash(), genesis, &block()).unwrap();
+
+	let json = serde_json::to_string(&head).unwrap();
+	assert_eq!(serde_json::from_str::<HeadData>(&json).unwrap(), head);
+}
+
+#[test]
+fn block_round_trips_through_json() {
+	let json = serde_json::to_string(&block()).unwrap();
+	let decoded: BlockData = serde_json::from_str(&json).unwrap();
+
+	assert_eq!(decoded.encode(), block().encode());
+}
+
+#[test]
+fn hashes_are_hex_strings() {
+	let head =
+		genesis_head_with(0, ArithmeticMode::Wrapping, HashMode::Blake3, StateCommitment::Value);
+	let json = serde_json::to_value(&head).unwrap();
+
+	assert_eq!(json["parent_hash"], format!("0x{}", "00".repeat(32)));
+	assert_eq!(json["post_state"], format!("0x{}", hex(&head.post_state)));
+	assert_eq!(json["history"], serde_json::Value::Null);
+}
+
+fn hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
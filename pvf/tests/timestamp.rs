@@ -0,0 +1,62 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Checks that the timestamps of a chain strictly increase.
+
+use pba_pvf::{execute, genesis_head, ArithmeticMode, BlockData, ExecuteError, HeadData};
+
+fn block_at(timestamp: u64) -> BlockData {
+	BlockData { state: 0, timestamp, ..Default::default() }
+}
+
+/// A head at `timestamp`, built on top of genesis.
+fn head_at(timestamp: u64) -> HeadData {
+	let genesis = genesis_head(0, ArithmeticMode::Wrapping);
+	execute(genesis.hash(), genesis, &block_at(timestamp)).unwrap()
+}
+
+#[test]
+fn genesis_starts_at_zero() {
+	assert_eq!(genesis_head(0, ArithmeticMode::Wrapping).timestamp, 0);
+}
+
+#[test]
+fn increasing_timestamp_is_accepted() {
+	let parent = head_at(10);
+
+	let head = execute(parent.hash(), parent, &block_at(11)).unwrap();
+	assert_eq!(head.timestamp, 11);
+}
+
+#[test]
+fn equal_timestamp_is_rejected() {
+	let parent = head_at(10);
+
+	assert_eq!(
+		execute(parent.hash(), parent, &block_at(10)),
+		Err(ExecuteError::TimestampNotIncreasing),
+	);
+}
+
+#[test]
+fn decreasing_timestamp_is_rejected() {
+	let parent = head_at(10);
+
+	assert_eq!(
+		execute(parent.hash(), parent, &block_at(9)),
+		Err(ExecuteError::TimestampNotIncreasing),
+	);
+}
@@ -0,0 +1,99 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Decoding and migration of heads of older versions.
+
+use parity_scale_codec::{Decode, Encode};
+use pba_pvf::{
+	execute, genesis_head, ArithmeticMode, BlockData, ExecuteError, HashMode, HeadData, HeadDataV4,
+	Op, VersionedHeadData, HEAD_DATA_VERSION,
+};
+
+fn v4_genesis() -> HeadDataV4 {
+	let genesis = genesis_head(0, ArithmeticMode::Wrapping);
+	HeadDataV4 {
+		number: genesis.number,
+		parent_hash: genesis.parent_hash,
+		post_state: genesis.post_state,
+		extrinsics_root: genesis.extrinsics_root,
+		events_root: genesis.events_root,
+		timestamp: genesis.timestamp,
+		hash_mode: HashMode::Blake3,
+	}
+}
+
+#[test]
+fn versioned_heads_round_trip() {
+	for head in [
+		VersionedHeadData::V4(v4_genesis()),
+		VersionedHeadData::V5(genesis_head(7, ArithmeticMode::Checked)),
+	] {
+		let encoded = head.encode();
+		assert_eq!(encoded[0], head.version());
+		assert_eq!(VersionedHeadData::decode(&mut &encoded[..]).unwrap(), head);
+	}
+}
+
+#[test]
+fn current_head_encodes_as_its_version() {
+	let head = genesis_head(7, ArithmeticMode::Checked);
+
+	assert_eq!(VersionedHeadData::from(head.clone()).encode(), head.encode());
+	assert_eq!(head.encode()[0], HEAD_DATA_VERSION);
+}
+
+#[test]
+fn v4_head_is_migrated_on_decoding() {
+	let encoded = VersionedHeadData::V4(v4_genesis()).encode();
+
+	let head = HeadData::decode(&mut &encoded[..]).unwrap();
+	assert_eq!(head, v4_genesis().migrate());
+	assert_eq!(head, genesis_head(0, ArithmeticMode::Wrapping));
+	assert_eq!(head.history, None);
+}
+
+#[test]
+fn blocks_build_on_a_v4_head() {
+	let encoded = VersionedHeadData::V4(v4_genesis()).encode();
+	let parent = VersionedHeadData::decode(&mut &encoded[..]).unwrap();
+	let block = BlockData { state: 0, ops: vec![Op::Add(3)], timestamp: 1, ..Default::default() };
+
+	// The child refers to the bytes the relay chain stores for its parent.
+	let parent_hash = HashMode::Blake3.hash(&encoded);
+	assert_eq!(parent.hash(), parent_hash);
+	let head = execute(parent_hash, parent, &block).unwrap();
+	assert_eq!(head.parent_hash, parent_hash);
+	assert_eq!(head.encode()[0], HEAD_DATA_VERSION);
+}
+
+#[test]
+fn hash_of_the_migrated_head_is_rejected_for_a_v4_parent() {
+	let parent = VersionedHeadData::V4(v4_genesis());
+	let migrated = parent.clone().into_latest();
+	let block = BlockData { state: 0, ops: vec![Op::Add(3)], timestamp: 1, ..Default::default() };
+
+	assert_ne!(migrated.hash(), parent.hash());
+	assert_eq!(execute(migrated.hash(), parent, &block), Err(ExecuteError::ParentHashMismatch));
+}
+
+#[test]
+fn unknown_version_is_rejected() {
+	let mut encoded = genesis_head(0, ArithmeticMode::Wrapping).encode();
+	encoded[0] = HEAD_DATA_VERSION + 1;
+
+	assert!(VersionedHeadData::decode(&mut &encoded[..]).is_err());
+	assert!(HeadData::decode(&mut &encoded[..]).is_err());
+}